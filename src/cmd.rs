@@ -1,8 +1,8 @@
 use clap::Parser;
 
-use crate::tree::ContentType;
+use otree::encoding::Encoding;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(author, about)]
 #[command(disable_version_flag = true)]
 pub struct CommandArgs {
@@ -15,11 +15,16 @@ pub struct CommandArgs {
     #[clap(long)]
     pub config: Option<String>,
 
-    /// The data content type. If not provided, we will try to determine it based on the file
-    /// extension. If reading data from stdin or the file extension is not standard, this
-    /// option must be set.
+    /// The data content type, can be json, jsonc, yaml, toml or the formats in config. If
+    /// not provided, we will try to determine it based on the file extension. If reading
+    /// data from stdin or the file extension is not standard, this option must be set.
     #[clap(short, long)]
-    pub content_type: Option<ContentType>,
+    pub content_type: Option<String>,
+
+    /// The data encoding. By default, we detect it with the BOM, and fallback to utf-16,
+    /// utf-8 and latin1 in order when there is no BOM.
+    #[clap(short, long, default_value = "auto")]
+    pub encoding: Encoding,
 
     /// Force to no render the header.
     #[clap(long)]
@@ -41,6 +46,40 @@ pub struct CommandArgs {
     #[clap(short, long)]
     pub size: Option<u16>,
 
+    /// Recover the json or yaml data cut off or broken in the middle as much as possible,
+    /// rather than refusing to open it. The skipped content is shown as an error node.
+    #[clap(long)]
+    pub lenient: bool,
+
+    /// When the data cannot be parsed, open it in the TUI at the error position, rather
+    /// than printing the error.
+    #[clap(long)]
+    pub open_error: bool,
+
+    /// Run the key and mouse events in the script file without a terminal, and dump the
+    /// rendered screen, see the document of `Script` for the syntax. This is used to
+    /// reproduce the TUI behavior deterministically.
+    #[clap(long)]
+    pub script: Option<String>,
+
+    /// The file to write the screens dumped by the script. If not provided, write them to
+    /// stdout.
+    #[clap(long)]
+    pub dump_screen: Option<String>,
+
+    /// The screen size to run the script, in the format `<width>x<height>`.
+    #[clap(long, default_value = "100x30", value_parser = parse_screen_size)]
+    pub screen_size: (u16, u16),
+
+    /// Dump the screen after each step of the script, rather than only at the end and
+    /// the `dump` steps.
+    #[clap(long)]
+    pub dump_each_step: bool,
+
+    /// Dump the colors and modifiers of the screen cells.
+    #[clap(long)]
+    pub dump_styles: bool,
+
     /// Show loaded config (in toml) and exit.
     #[clap(long)]
     pub show_config: bool,
@@ -53,3 +92,15 @@ pub struct CommandArgs {
     #[clap(short, long)]
     pub version: bool,
 }
+
+fn parse_screen_size(size: &str) -> Result<(u16, u16), String> {
+    let parsed = size
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+    match parsed {
+        Some((width, height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!(
+            "invalid screen size '{size}', should be like '100x30'"
+        )),
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use anyhow::{Context, Result};
 use ratatui::style::{Style, Stylize};
 use serde::{Deserialize, Serialize};
@@ -33,8 +35,8 @@ pub struct Colors {
 
 generate_colors_parse!(Colors, header, tree, item, data, focus_border);
 
-impl Colors {
-    pub fn default() -> Self {
+impl Default for Colors {
+    fn default() -> Self {
         Self {
             header: Self::default_header(),
             tree: TreeColors::default(),
@@ -43,7 +45,9 @@ impl Colors {
             focus_border: Self::default_focus_boder(),
         }
     }
+}
 
+impl Colors {
     fn default_header() -> Color {
         Color::new("", "", true, false)
     }
@@ -128,11 +132,38 @@ pub struct ItemColors {
     #[serde(default = "ItemColors::default_type")]
     pub type_obj: Color,
 
+    #[serde(default = "ItemColors::default_type")]
+    pub type_datetime: Color,
+
+    #[serde(default = "ItemColors::default_type")]
+    pub type_doc: Color,
+
+    #[serde(default = "ItemColors::default_type_error")]
+    pub type_error: Color,
+
     #[serde(default = "ItemColors::default_description")]
     pub description: Color,
 
     #[serde(default = "ItemColors::default_null")]
     pub null: Color,
+
+    #[serde(default = "ItemColors::default_anchor")]
+    pub anchor: Color,
+
+    #[serde(default = "ItemColors::default_warning")]
+    pub warning: Color,
+
+    #[serde(default = "ItemColors::default_comment")]
+    pub comment: Color,
+
+    #[serde(default = "ItemColors::default_embedded")]
+    pub embedded: Color,
+
+    #[serde(default = "ItemColors::default_search_match")]
+    pub search_match: Color,
+
+    #[serde(default = "TagColors::default")]
+    pub tag: TagColors,
 }
 
 generate_colors_parse!(
@@ -144,8 +175,17 @@ generate_colors_parse!(
     type_num,
     type_arr,
     type_obj,
+    type_datetime,
+    type_doc,
+    type_error,
     description,
-    null
+    null,
+    anchor,
+    warning,
+    comment,
+    embedded,
+    search_match,
+    tag
 );
 
 impl ItemColors {
@@ -158,8 +198,17 @@ impl ItemColors {
             type_num: Self::default_type(),
             type_arr: Self::default_type(),
             type_obj: Self::default_type(),
+            type_datetime: Self::default_type(),
+            type_doc: Self::default_type(),
+            type_error: Self::default_type_error(),
             description: Self::default_description(),
             null: Self::default_null(),
+            anchor: Self::default_anchor(),
+            warning: Self::default_warning(),
+            comment: Self::default_comment(),
+            embedded: Self::default_embedded(),
+            search_match: Self::default_search_match(),
+            tag: TagColors::default(),
         }
     }
 
@@ -167,6 +216,10 @@ impl ItemColors {
         Color::new("cyan", "", true, true)
     }
 
+    fn default_type_error() -> Color {
+        Color::new("red", "", true, true)
+    }
+
     fn default_description() -> Color {
         Color::new("dark_gray", "", false, false)
     }
@@ -174,6 +227,64 @@ impl ItemColors {
     fn default_null() -> Color {
         Color::new("dark_gray", "", false, true)
     }
+
+    fn default_anchor() -> Color {
+        Color::new("yellow", "", false, false)
+    }
+
+    fn default_warning() -> Color {
+        Color::new("red", "", true, false)
+    }
+
+    fn default_comment() -> Color {
+        Color::new("green", "", false, true)
+    }
+
+    fn default_embedded() -> Color {
+        Color::new("blue", "", false, false)
+    }
+
+    fn default_search_match() -> Color {
+        Color::new("black", "yellow", false, false)
+    }
+}
+
+/// The colors for yaml tags, the `default` is used for tags without a specific color, the
+/// other fields are the tag names, like `"!Ref" = {fg = "green"}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagColors {
+    #[serde(default = "TagColors::default_tag")]
+    pub default: Color,
+
+    #[serde(flatten)]
+    pub tags: HashMap<String, Color>,
+}
+
+impl TagColors {
+    fn default() -> Self {
+        Self {
+            default: Self::default_tag(),
+            tags: HashMap::new(),
+        }
+    }
+
+    fn default_tag() -> Color {
+        Color::new("magenta", "", false, true)
+    }
+
+    pub fn get(&self, tag: &str) -> &Color {
+        self.tags.get(tag).unwrap_or(&self.default)
+    }
+
+    fn parse(&mut self) -> Result<()> {
+        self.default.parse().context("parse default tag color")?;
+        for (tag, color) in self.tags.iter_mut() {
+            color
+                .parse()
+                .with_context(|| format!("parse color for tag '{tag}'"))?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
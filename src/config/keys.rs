@@ -6,9 +6,9 @@ use serde::{Deserialize, Serialize};
 
 macro_rules! generate_keys_default {
     ($($field:ident => $value:expr),+) => {
-        impl Keys {
+        impl Default for Keys {
             ::paste::paste! {
-                pub fn default() -> Self {
+                fn default() -> Self {
                     Self {
                         $(
                             $field: Self::[<default_ $field>](),
@@ -17,6 +17,9 @@ macro_rules! generate_keys_default {
                     }
                 }
             }
+        }
+
+        impl Keys {
 
             $(
                 ::paste::paste! {
@@ -118,6 +121,26 @@ impl Key {
         }
     }
 
+    fn to_event(self) -> KeyEvent {
+        let (code, modifiers) = match self {
+            Self::Char(char) => (KeyCode::Char(char), KeyModifiers::NONE),
+            Self::Ctrl(char) => (KeyCode::Char(char), KeyModifiers::CONTROL),
+            Self::Alt(char) => (KeyCode::Char(char), KeyModifiers::ALT),
+            Self::F(n) => (KeyCode::F(n), KeyModifiers::NONE),
+            Self::Backspace => (KeyCode::Backspace, KeyModifiers::NONE),
+            Self::Enter => (KeyCode::Enter, KeyModifiers::NONE),
+            Self::Left => (KeyCode::Left, KeyModifiers::NONE),
+            Self::Right => (KeyCode::Right, KeyModifiers::NONE),
+            Self::Up => (KeyCode::Up, KeyModifiers::NONE),
+            Self::Down => (KeyCode::Down, KeyModifiers::NONE),
+            Self::PageUp => (KeyCode::PageUp, KeyModifiers::NONE),
+            Self::PageDown => (KeyCode::PageDown, KeyModifiers::NONE),
+            Self::Tab => (KeyCode::Tab, KeyModifiers::NONE),
+            Self::Esc => (KeyCode::Esc, KeyModifiers::NONE),
+        };
+        KeyEvent::new(code, modifiers)
+    }
+
     fn parse(key: &str) -> Result<Self> {
         if !key.starts_with('<') {
             if key.len() != 1 {
@@ -223,6 +246,17 @@ pub struct Keys {
     #[serde(default = "Keys::default_reset")]
     pub reset: Vec<String>,
 
+    #[serde(default = "Keys::default_jump_anchor")]
+    pub jump_anchor: Vec<String>,
+
+    #[serde(default = "Keys::default_expand_embedded")]
+    pub expand_embedded: Vec<String>,
+
+    #[serde(default = "Keys::default_next_document")]
+    pub next_document: Vec<String>,
+    #[serde(default = "Keys::default_prev_document")]
+    pub prev_document: Vec<String>,
+
     #[serde(default = "Keys::default_page_up")]
     pub page_up: Vec<String>,
     #[serde(default = "Keys::default_page_down")]
@@ -240,6 +274,22 @@ pub struct Keys {
     #[serde(default = "Keys::default_switch")]
     pub switch: Vec<String>,
 
+    #[serde(default = "Keys::default_search")]
+    pub search: Vec<String>,
+    #[serde(default = "Keys::default_search_next")]
+    pub search_next: Vec<String>,
+    #[serde(default = "Keys::default_search_prev")]
+    pub search_prev: Vec<String>,
+
+    #[serde(default = "Keys::default_filter")]
+    pub filter: Vec<String>,
+
+    #[serde(default = "Keys::default_find_path")]
+    pub find_path: Vec<String>,
+
+    #[serde(default = "Keys::default_reload_config")]
+    pub reload_config: Vec<String>,
+
     #[serde(default = "Keys::default_quit")]
     pub quit: Vec<String>,
 
@@ -259,12 +309,22 @@ generate_keys_default!(
     close_parent => ["<backspace>"],
     change_root => ["r"],
     reset => ["<esc>"],
+    jump_anchor => ["a"],
+    expand_embedded => ["e"],
+    next_document => ["}"],
+    prev_document => ["{"],
     page_up => ["<page-up>", "<ctrl-y>"],
     page_down => ["<page-down>", "<ctrl-e>"],
     change_layout => ["v"],
     tree_scale_up => ["["],
     tree_scale_down => ["]"],
     switch => ["<tab>"],
+    search => ["/"],
+    search_next => ["n"],
+    search_prev => ["N"],
+    filter => ["f"],
+    find_path => ["<ctrl-p>"],
+    reload_config => ["R"],
     quit => ["<ctrl-c>", "q"]
 );
 
@@ -280,12 +340,22 @@ generate_actions!(
     close_parent => CloseParent,
     change_root => ChangeRoot,
     reset => Reset,
+    jump_anchor => JumpAnchor,
+    expand_embedded => ExpandEmbedded,
+    next_document => NextDocument,
+    prev_document => PrevDocument,
     page_up => PageUp,
     page_down => PageDown,
     change_layout => ChangeLayout,
     tree_scale_up => TreeScaleUp,
     tree_scale_down => TreeScaleDown,
     switch => Switch,
+    search => Search,
+    search_next => SearchNext,
+    search_prev => SearchPrev,
+    filter => Filter,
+    find_path => FindPath,
+    reload_config => ReloadConfig,
     quit => Quit
 );
 
@@ -302,3 +372,9 @@ impl Keys {
         None
     }
 }
+
+/// Parse the key in the config syntax, like `j` or `<ctrl-e>`, to the key event pressing
+/// it.
+pub fn parse_key_event(key: &str) -> Result<KeyEvent> {
+    Key::parse(key).map(Key::to_event)
+}
@@ -2,11 +2,20 @@ pub mod colors;
 pub mod keys;
 pub mod types;
 
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
 use std::path::PathBuf;
-use std::{env, fs, io};
+use std::process::{Command, Stdio};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::{env, fs, io, thread};
 
 use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::tree::{ContentType, Progress, SearchTarget};
 
 use self::colors::Colors;
 use self::keys::Keys;
@@ -20,6 +29,15 @@ pub struct Config {
     #[serde(default = "Header::default")]
     pub header: Header,
 
+    #[serde(default = "Document::default")]
+    pub document: Document,
+
+    #[serde(default = "Data::default")]
+    pub data: Data,
+
+    #[serde(default = "Search::default")]
+    pub search: Search,
+
     #[serde(default = "Colors::default")]
     pub colors: Colors,
 
@@ -28,6 +46,9 @@ pub struct Config {
 
     #[serde(default = "Keys::default")]
     pub keys: Keys,
+
+    #[serde(default = "Config::default_formats")]
+    pub formats: BTreeMap<String, Format>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,7 +60,7 @@ pub struct Layout {
     pub tree_size: u16,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LayoutDirection {
     #[serde(rename = "vertical")]
     Vertical,
@@ -56,6 +77,80 @@ pub struct Header {
     pub format: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document {
+    /// The label of each document in a multi-document yaml stream. The `{field}` will be
+    /// replaced with the field value in the document, use `.` to access nested fields.
+    #[serde(default = "Document::default_label")]
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Data {
+    /// The maximum size in bytes of the data to be loaded as a whole, to ensure the TUI
+    /// performance.
+    #[serde(default = "Data::default_max_size")]
+    pub max_size: usize,
+
+    /// Open the json file larger than `max_size` in huge-file mode, rather than refusing
+    /// it. The file is memory-mapped, and only the opened nodes are parsed.
+    #[serde(default = "Data::default_huge_file")]
+    pub huge_file: bool,
+}
+
+/// The default options of the search, they can be switched in the search input.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Search {
+    /// Treat the query as a regex, rather than plain text.
+    #[serde(default = "Search::default_regex")]
+    pub regex: bool,
+
+    #[serde(default = "Search::default_case_sensitive")]
+    pub case_sensitive: bool,
+
+    /// Match the query against the field names, the scalar values, or both.
+    #[serde(default = "Search::default_target")]
+    pub target: SearchTarget,
+}
+
+/// An external format, the data is converted by a command before parsing, like
+/// `hcl2json` for hcl.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Format {
+    /// The file extensions of the format, to determine the format by file path.
+    #[serde(default = "Format::default_extensions")]
+    pub extensions: Vec<String>,
+
+    /// The converter command, it reads the data from stdin, and writes the converted data
+    /// to stdout.
+    pub command: String,
+
+    #[serde(default = "Format::default_args")]
+    pub args: Vec<String>,
+
+    /// The content type of the command output.
+    #[serde(default = "Format::default_output")]
+    pub output: ContentType,
+}
+
+impl Default for Config {
+    /// The default config, it should be checked by [`Config::parse`] before use, like the
+    /// config loaded from file.
+    fn default() -> Self {
+        Self {
+            layout: Layout::default(),
+            header: Header::default(),
+            document: Document::default(),
+            data: Data::default(),
+            search: Search::default(),
+            colors: Colors::default(),
+            types: Types::default(),
+            keys: Keys::default(),
+            formats: Self::default_formats(),
+        }
+    }
+}
+
 impl Config {
     pub const MIN_LAYOUT_TREE_SIZE: u16 = 10;
     pub const MAX_LAYOUT_TREE_SIZE: u16 = 80;
@@ -73,6 +168,8 @@ impl Config {
         toml::from_str(&data).context("parse config toml")
     }
 
+    /// Check the config, and build the styles and key bindings from it. This must be
+    /// called before the config is used, including the config built in code.
     pub fn parse(&mut self) -> Result<()> {
         if self.layout.tree_size < Self::MIN_LAYOUT_TREE_SIZE
             || self.layout.tree_size > Self::MAX_LAYOUT_TREE_SIZE
@@ -84,12 +181,53 @@ impl Config {
             );
         }
 
+        for (name, format) in self.formats.iter() {
+            if ContentType::from_str(name, true).is_ok() {
+                bail!("invalid format '{name}', the name is used by the builtin content type");
+            }
+            if format.command.is_empty() {
+                bail!("invalid format '{name}', the command cannot be empty");
+            }
+        }
+
         self.colors.parse()?;
         self.keys.parse()?;
         Ok(())
     }
 
-    fn get_path(path: Option<String>) -> Result<Option<PathBuf>> {
+    /// Returns the content type to parse the data of format `name`, and the format if it
+    /// is an external format. The name can be a builtin content type or a format in
+    /// config.
+    pub fn get_format(&self, name: &str) -> Option<(ContentType, Option<&Format>)> {
+        if let Ok(content_type) = ContentType::from_str(name, true) {
+            return Some((content_type, None));
+        }
+        let format = self.formats.get(name)?;
+        Some((format.output, Some(format)))
+    }
+
+    /// Returns the format name of the file extension.
+    pub fn get_format_name(&self, ext: &str) -> Option<String> {
+        let name = match ext {
+            "json" => "json",
+            "jsonc" => "jsonc",
+            "yaml" | "yml" => "yaml",
+            "toml" => "toml",
+            _ => {
+                let (name, _) = self
+                    .formats
+                    .iter()
+                    .find(|(_, format)| format.extensions.iter().any(|e| e == ext))?;
+                name
+            }
+        };
+        Some(name.to_string())
+    }
+
+    /// Returns the path of the config file to load, `None` if there is no config file.
+    /// The `path` is from the command line, it takes precedence over the environment
+    /// variable `OTREE_CONFIG` and `~/.config/otree.toml`.
+    pub fn get_path(path: Option<String>) -> Result<Option<PathBuf>> {
         if let Some(path) = path {
             return Ok(Some(PathBuf::from(path)));
         }
@@ -114,14 +252,8 @@ impl Config {
         }
     }
 
-    pub fn default() -> Self {
-        Self {
-            layout: Layout::default(),
-            header: Header::default(),
-            colors: Colors::default(),
-            types: Types::default(),
-            keys: Keys::default(),
-        }
+    fn default_formats() -> BTreeMap<String, Format> {
+        BTreeMap::new()
     }
 
     pub fn show(&self) -> Result<()> {
@@ -164,3 +296,217 @@ impl Header {
         "{version} - {data_source} ({content_type}) - {data_size}".to_string()
     }
 }
+
+impl Data {
+    fn default() -> Self {
+        Self {
+            max_size: Self::default_max_size(),
+            huge_file: Self::default_huge_file(),
+        }
+    }
+
+    fn default_max_size() -> usize {
+        10 * 1024 * 1024
+    }
+
+    fn default_huge_file() -> bool {
+        true
+    }
+}
+
+impl Search {
+    fn default() -> Self {
+        Self {
+            regex: Self::default_regex(),
+            case_sensitive: Self::default_case_sensitive(),
+            target: Self::default_target(),
+        }
+    }
+
+    fn default_regex() -> bool {
+        false
+    }
+
+    fn default_case_sensitive() -> bool {
+        false
+    }
+
+    fn default_target() -> SearchTarget {
+        SearchTarget::All
+    }
+}
+
+impl Format {
+    /// The interval to check whether the converter command exits.
+    const WAIT_INTERVAL: Duration = Duration::from_millis(20);
+
+    fn default_extensions() -> Vec<String> {
+        vec![]
+    }
+
+    fn default_args() -> Vec<String> {
+        vec![]
+    }
+
+    fn default_output() -> ContentType {
+        ContentType::Json
+    }
+
+    /// Pipe the data through the converter command, returns the command output. The
+    /// command is killed if the loading is cancelled.
+    pub fn convert(&self, data: Vec<u8>, progress: &Progress) -> Result<Vec<u8>> {
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("run command '{}'", self.command))?;
+
+        // Write stdin and read the outputs in other threads, otherwise the command may
+        // block on writing a large output, while we are blocked on writing the input.
+        // This also lets us wait for the command and watch the cancellation together.
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = thread::spawn(move || stdin.write_all(&data));
+        let stdout = read_in_thread(child.stdout.take().unwrap());
+        let stderr = read_in_thread(child.stderr.take().unwrap());
+
+        let status = loop {
+            if let Err(err) = progress.check() {
+                // The command may exit just now, the error is meaningless then.
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(err);
+            }
+            let status = child
+                .try_wait()
+                .with_context(|| format!("wait command '{}'", self.command))?;
+            match status {
+                Some(status) => break status,
+                None => thread::sleep(Self::WAIT_INTERVAL),
+            }
+        };
+        let stdout = stdout.join().unwrap();
+        let stderr = stderr.join().unwrap();
+        // The command may exit without reading all the input, the error is meaningless
+        // if it succeeds.
+        let write_result = writer.join().unwrap();
+
+        if !status.success() {
+            let stderr = stderr.unwrap_or_default();
+            let stderr = String::from_utf8_lossy(&stderr);
+            bail!(
+                "command '{}' failed with {}: {}",
+                self.command,
+                status,
+                stderr.trim()
+            );
+        }
+        write_result.with_context(|| format!("write data to command '{}'", self.command))?;
+
+        stdout.with_context(|| format!("read output of command '{}'", self.command))
+    }
+}
+
+/// Read all the data from the reader in a new thread.
+fn read_in_thread(mut reader: impl Read + Send + 'static) -> JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Ok(data)
+    })
+}
+
+impl Document {
+    fn default() -> Self {
+        Self {
+            label: Self::default_label(),
+        }
+    }
+
+    fn default_label() -> String {
+        String::from("{kind}/{metadata.name}")
+    }
+
+    /// Format the label for the document, returns `None` if no field in the label can be
+    /// found in the document.
+    pub fn format_label(&self, document: &Value) -> Option<String> {
+        let mut label = String::with_capacity(self.label.len());
+        let mut found = false;
+        let mut rest = self.label.as_str();
+        while let Some(start) = rest.find('{') {
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => break,
+            };
+            label.push_str(&rest[..start]);
+
+            let field = &rest[start + 1..end];
+            let value = field
+                .split('.')
+                .try_fold(document, |value, key| value.get(key));
+            match value {
+                Some(Value::String(s)) => {
+                    label.push_str(s);
+                    found = true;
+                }
+                Some(value) if !value.is_array() && !value.is_object() => {
+                    label.push_str(&value.to_string());
+                    found = true;
+                }
+                _ => {}
+            }
+
+            rest = &rest[end + 1..];
+        }
+        label.push_str(rest);
+
+        if found {
+            Some(label)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn format(command: &str, args: &[&str]) -> Format {
+        Format {
+            extensions: vec![],
+            command: command.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            output: ContentType::Json,
+        }
+    }
+
+    #[test]
+    fn test_convert() {
+        let format = format("cat", &[]);
+        let output = format
+            .convert(b"[1]".to_vec(), &Progress::default())
+            .unwrap();
+        assert_eq!(output, b"[1]");
+    }
+
+    #[test]
+    fn test_convert_cancelled() {
+        let format = format("sleep", &["10"]);
+        let progress = Progress::default();
+        let start = Instant::now();
+        let result = thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(100));
+                progress.cancel();
+            });
+            format.convert(vec![], &progress)
+        });
+        assert_eq!(result.unwrap_err().to_string(), "loading is cancelled");
+        // The command is killed rather than waited.
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
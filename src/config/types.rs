@@ -2,9 +2,9 @@ use serde::{Deserialize, Serialize};
 
 macro_rules! generate_types_default {
     ($StructName:ident, $($field:ident),+) => {
-        impl $StructName {
+        impl Default for $StructName {
             ::paste::paste! {
-                pub fn default() -> Self {
+                fn default() -> Self {
                     Self {
                         $(
                             $field: Self::[<default_ $field>](),
@@ -12,6 +12,9 @@ macro_rules! generate_types_default {
                     }
                 }
             }
+        }
+
+        impl $StructName {
 
             $(
                 ::paste::paste! {
@@ -43,6 +46,15 @@ pub struct Types {
 
     #[serde(default = "Types::default_obj")]
     pub obj: String,
+
+    #[serde(default = "Types::default_datetime")]
+    pub datetime: String,
+
+    #[serde(default = "Types::default_doc")]
+    pub doc: String,
+
+    #[serde(default = "Types::default_error")]
+    pub error: String,
 }
 
-generate_types_default!(Types, str, null, bool, num, arr, obj);
+generate_types_default!(Types, str, null, bool, num, arr, obj, datetime, doc, error);
@@ -0,0 +1,243 @@
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Encoding {
+    /// Detect the encoding by BOM, fallback to utf16, utf8 and latin1 in order.
+    Auto,
+    Utf8,
+    Utf16le,
+    Utf16be,
+    Utf32le,
+    Utf32be,
+    Latin1,
+}
+
+impl Encoding {
+    const BOMS: [(&'static [u8], Self); 5] = [
+        // The utf32 BOMs must be checked before utf16, since the utf32le BOM starts
+        // with the utf16le BOM.
+        (&[0xFF, 0xFE, 0x00, 0x00], Self::Utf32le),
+        (&[0x00, 0x00, 0xFE, 0xFF], Self::Utf32be),
+        (&[0xEF, 0xBB, 0xBF], Self::Utf8),
+        (&[0xFF, 0xFE], Self::Utf16le),
+        (&[0xFE, 0xFF], Self::Utf16be),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Utf8 => "utf-8",
+            Self::Utf16le => "utf-16le",
+            Self::Utf16be => "utf-16be",
+            Self::Utf32le => "utf-32le",
+            Self::Utf32be => "utf-32be",
+            Self::Latin1 => "latin1",
+        }
+    }
+
+    /// Decode the data to string. Returns the decoded string and the encoding actually
+    /// used, which is useful when the encoding is [`Encoding::Auto`].
+    pub fn decode(self, data: Vec<u8>) -> Result<(String, Self)> {
+        let (data, bom) = Self::strip_bom(data);
+        if let Some(bom) = bom {
+            if !matches!(self, Self::Auto) && self != bom {
+                bail!(
+                    "the data has a {} BOM, but the encoding is specified as {}",
+                    bom.name(),
+                    self.name()
+                );
+            }
+            return Ok((bom.decode_raw(&data)?, bom));
+        }
+
+        if !matches!(self, Self::Auto) {
+            return Ok((self.decode_raw(&data)?, self));
+        }
+
+        // Without BOM, we try the most common encodings one by one. The utf16 guess is
+        // based on the zero bytes, an ascii-heavy utf16 document has lots of them while a
+        // utf8 or latin1 text file almost never contains any. It must go first, since
+        // the ascii-heavy utf16 data is also valid utf8.
+        if let Some(utf16) = Self::guess_utf16(&data) {
+            if let Ok(s) = utf16.decode_raw(&data) {
+                return Ok((s, utf16));
+            }
+        }
+        let data = match String::from_utf8(data) {
+            Ok(s) => return Ok((s, Self::Utf8)),
+            Err(err) => err.into_bytes(),
+        };
+
+        Ok((Self::Latin1.decode_raw(&data)?, Self::Latin1))
+    }
+
+    fn strip_bom(mut data: Vec<u8>) -> (Vec<u8>, Option<Self>) {
+        for (bom, encoding) in Self::BOMS {
+            if data.starts_with(bom) {
+                data.drain(..bom.len());
+                return (data, Some(encoding));
+            }
+        }
+        (data, None)
+    }
+
+    fn guess_utf16(data: &[u8]) -> Option<Self> {
+        if data.len() < 2 || !data.len().is_multiple_of(2) {
+            return None;
+        }
+
+        let mut even_zeros = 0;
+        let mut odd_zeros = 0;
+        for (idx, byte) in data.iter().enumerate() {
+            if *byte != 0 {
+                continue;
+            }
+            if idx % 2 == 0 {
+                even_zeros += 1;
+            } else {
+                odd_zeros += 1;
+            }
+        }
+
+        let half = data.len() / 2;
+        // More than a quarter of the code units contain a zero byte at the same side.
+        if odd_zeros * 4 > half && even_zeros == 0 {
+            Some(Self::Utf16le)
+        } else if even_zeros * 4 > half && odd_zeros == 0 {
+            Some(Self::Utf16be)
+        } else {
+            None
+        }
+    }
+
+    fn decode_raw(&self, data: &[u8]) -> Result<String> {
+        match self {
+            Self::Auto | Self::Utf8 => match std::str::from_utf8(data) {
+                Ok(s) => Ok(s.to_string()),
+                Err(err) => bail!("invalid utf-8 data: {err}"),
+            },
+            Self::Utf16le | Self::Utf16be => {
+                if !data.len().is_multiple_of(2) {
+                    bail!("invalid {} data, odd number of bytes", self.name());
+                }
+                let units: Vec<u16> = data
+                    .chunks_exact(2)
+                    .map(|chunk| {
+                        let bytes = [chunk[0], chunk[1]];
+                        if matches!(self, Self::Utf16le) {
+                            u16::from_le_bytes(bytes)
+                        } else {
+                            u16::from_be_bytes(bytes)
+                        }
+                    })
+                    .collect();
+                match String::from_utf16(&units) {
+                    Ok(s) => Ok(s),
+                    Err(err) => bail!("invalid {} data: {err}", self.name()),
+                }
+            }
+            Self::Utf32le | Self::Utf32be => {
+                if !data.len().is_multiple_of(4) {
+                    bail!(
+                        "invalid {} data, bytes length is not a multiple of 4",
+                        self.name()
+                    );
+                }
+                let mut s = String::with_capacity(data.len() / 4);
+                for chunk in data.chunks_exact(4) {
+                    let bytes = [chunk[0], chunk[1], chunk[2], chunk[3]];
+                    let code = if matches!(self, Self::Utf32le) {
+                        u32::from_le_bytes(bytes)
+                    } else {
+                        u32::from_be_bytes(bytes)
+                    };
+                    match char::from_u32(code) {
+                        Some(c) => s.push(c),
+                        None => bail!("invalid {} data, bad code point {code:#x}", self.name()),
+                    }
+                }
+                Ok(s)
+            }
+            // Latin1 maps every byte to the unicode code point with the same value, so the
+            // decoding never fails.
+            Self::Latin1 => Ok(data.iter().map(|b| *b as char).collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn utf16be(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    #[test]
+    fn test_decode_bom() {
+        let data = [&[0xEF, 0xBB, 0xBF][..], "{\"a\": 1}".as_bytes()].concat();
+        let (s, encoding) = Encoding::Auto.decode(data).unwrap();
+        assert_eq!(s, "{\"a\": 1}");
+        assert_eq!(encoding, Encoding::Utf8);
+
+        let data = [&[0xFF, 0xFE][..], &utf16le("a: é")].concat();
+        let (s, encoding) = Encoding::Auto.decode(data).unwrap();
+        assert_eq!(s, "a: é");
+        assert_eq!(encoding, Encoding::Utf16le);
+
+        let data = [&[0xFE, 0xFF][..], &utf16be("a: é")].concat();
+        let (s, encoding) = Encoding::Auto.decode(data).unwrap();
+        assert_eq!(s, "a: é");
+        assert_eq!(encoding, Encoding::Utf16be);
+
+        let data: Vec<u8> = [0xFF, 0xFE, 0x00, 0x00]
+            .into_iter()
+            .chain("ab".chars().flat_map(|c| (c as u32).to_le_bytes()))
+            .collect();
+        let (s, encoding) = Encoding::Auto.decode(data).unwrap();
+        assert_eq!(s, "ab");
+        assert_eq!(encoding, Encoding::Utf32le);
+    }
+
+    #[test]
+    fn test_decode_bom_mismatch() {
+        let data = [&[0xFF, 0xFE][..], &utf16le("a")].concat();
+        assert!(Encoding::Utf8.decode(data.clone()).is_err());
+        let (s, _) = Encoding::Utf16le.decode(data).unwrap();
+        assert_eq!(s, "a");
+    }
+
+    #[test]
+    fn test_decode_utf16_without_bom() {
+        let text = "{\"name\": \"otree\", \"items\": [1, 2, 3]}";
+        let (s, encoding) = Encoding::Auto.decode(utf16le(text)).unwrap();
+        assert_eq!(s, text);
+        assert_eq!(encoding, Encoding::Utf16le);
+
+        let (s, encoding) = Encoding::Auto.decode(utf16be(text)).unwrap();
+        assert_eq!(s, text);
+        assert_eq!(encoding, Encoding::Utf16be);
+    }
+
+    #[test]
+    fn test_decode_utf8_without_bom() {
+        let text = "key: 中文";
+        let (s, encoding) = Encoding::Auto.decode(text.as_bytes().to_vec()).unwrap();
+        assert_eq!(s, text);
+        assert_eq!(encoding, Encoding::Utf8);
+    }
+
+    #[test]
+    fn test_decode_latin1_fallback() {
+        // "café" in latin1, the 0xE9 byte is invalid in utf8.
+        let data = vec![b'c', b'a', b'f', 0xE9];
+        let (s, encoding) = Encoding::Auto.decode(data).unwrap();
+        assert_eq!(s, "café");
+        assert_eq!(encoding, Encoding::Latin1);
+    }
+}
@@ -0,0 +1,38 @@
+//! The tree viewer of otree as a library, to embed it in other ratatui apps.
+//!
+//! Build a [`tree::Tree`] from a json value or raw data, and show it with
+//! [`ui::App`], which draws into any area of a frame and handles the terminal events
+//! passed to it, the event loop is owned by the embedding app:
+//!
+//! ```no_run
+//! use otree::config::Config;
+//! use otree::encoding::Encoding;
+//! use otree::tree::{ContentType, Tree};
+//! use otree::ui::{App, Refresh};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let mut cfg = Config::default();
+//! cfg.layout.tree_size = 50;
+//! cfg.parse()?;
+//!
+//! let data = br#"{"name": "otree", "tags": ["tui", "json"]}"#.to_vec();
+//! let tree = Tree::from_bytes(&cfg, data, ContentType::Json, Encoding::Auto)?;
+//! let mut app = App::new(cfg, tree);
+//!
+//! let mut terminal = otree::ui::start()?;
+//! loop {
+//!     terminal.draw(|frame| app.draw(frame, frame.size()))?;
+//!     let event = crossterm::event::read()?;
+//!     if let Refresh::Quit = app.handle_event(&event) {
+//!         break;
+//!     }
+//! }
+//! otree::ui::restore(terminal)?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod config;
+pub mod encoding;
+pub mod tree;
+pub mod ui;
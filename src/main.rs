@@ -1,27 +1,29 @@
 mod cmd;
-mod config;
-mod tree;
-mod ui;
 mod version;
 
-use std::fs;
-use std::io;
-use std::io::Read;
+use std::fs::{self, File};
+use std::io::{self, Read, Stdout};
 use std::path::PathBuf;
-use std::process;
+use std::sync::Arc;
+use std::{panic, process, thread};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use clap::error::ErrorKind as ArgsErrorKind;
 use clap::Parser;
+use memmap2::Mmap;
+use ratatui::backend::CrosstermBackend;
+use ratatui::Terminal;
+
+use otree::config::LayoutDirection;
+use otree::config::{Config, Format};
+use otree::encoding::Encoding;
+use otree::tree::{ContentType, ParseError, Progress, Tree};
+use otree::ui::{self, App, DumpOptions, ErrorPage, HeaderContext, LoadingPage, Script};
 
 use crate::cmd::CommandArgs;
-use crate::config::Config;
-use crate::config::LayoutDirection;
-use crate::tree::{ContentType, Tree};
-use crate::ui::{App, HeaderContext};
 
-// Forbid large data size to ensure TUI performance
-const MAX_DATA_SIZE: usize = 10 * 1024 * 1024;
+/// The buffer size to read the data, the progress is updated after each read.
+const READ_BUFFER_SIZE: usize = 64 * 1024;
 
 fn run() -> Result<()> {
     let args = match CommandArgs::try_parse() {
@@ -47,35 +49,10 @@ fn run() -> Result<()> {
         return Ok(());
     }
 
-    let mut cfg = if args.ignore_config {
-        Config::default()
-    } else {
-        Config::load(args.config)?
-    };
-
     if args.vertical && args.horizontal {
         bail!("invalid command line args, the vertical and horizontal cannot be used together");
     }
-    if args.vertical {
-        cfg.layout.direction = LayoutDirection::Vertical;
-    }
-    if args.horizontal {
-        cfg.layout.direction = LayoutDirection::Horizontal;
-    }
-
-    if args.disable_header {
-        cfg.header.disable = true;
-    }
-
-    if let Some(format) = args.header_format {
-        cfg.header.format = format;
-    }
-
-    if let Some(size) = args.size {
-        cfg.layout.tree_size = size;
-    }
-
-    cfg.parse().context("parse config")?;
+    let cfg = load_config(&args)?;
 
     if args.show_config {
         return cfg.show();
@@ -85,8 +62,8 @@ fn run() -> Result<()> {
     // file extension. Another approach is to use file content (for example, if the file
     // content starts with '{', we can assume it is json). But this approach is not reliable
     // since the yaml is the superset of json, and the toml is not easy to determine.
-    let content_type = match args.content_type {
-        Some(content_type) => content_type,
+    let format_name = match args.content_type.clone() {
+        Some(name) => name,
         None => {
             if args.path.is_none() {
                 bail!("you must specify content type when reading data from stdin");
@@ -102,52 +79,60 @@ fn run() -> Result<()> {
                 bail!("invalid extension in file path");
             }
 
-            match ext.unwrap() {
-                "json" => ContentType::Json,
-                "yaml" | "yml" => ContentType::Yaml,
-                "toml" => ContentType::Toml,
-                _ => bail!("unsupported file type, please specify content type manually"),
+            match cfg.get_format_name(ext.unwrap()) {
+                Some(name) => name,
+                None => bail!("unsupported file type, please specify content type manually"),
             }
         }
     };
+    let (content_type, format) = match cfg.get_format(&format_name) {
+        Some(format) => format,
+        None => bail!("unsupported content type '{format_name}', it should be json, jsonc, yaml, toml or a format in config"),
+    };
 
-    let data = match args.path.as_ref() {
+    // The json file larger than the limit is memory-mapped and parsed on demand, rather
+    // than read and parsed as a whole.
+    let mut huge = false;
+    match args.path.as_ref() {
         Some(path) => {
-            let path = PathBuf::from(path);
-            fs::read(path).context("read file")?
+            let size = fs::metadata(path).context("get file metadata")?.len() as usize;
+            if size > cfg.data.max_size {
+                huge = cfg.data.huge_file && content_type == ContentType::Json && format.is_none();
+                if !huge {
+                    check_size(&cfg, size)?;
+                }
+                if !matches!(args.encoding, Encoding::Auto | Encoding::Utf8) {
+                    bail!("the huge-file mode only supports utf-8 data");
+                }
+            }
         }
         None => {
-            if cfg!(target_os = "macos") {
+            if cfg!(target_os = "macos") && args.script.is_none() {
                 // Read from stdin is not supported on macos.
                 // See: <https://github.com/crossterm-rs/crossterm/issues/500>
                 bail!(
                     "reading data from stdin is not supported on macos, please read it from file"
                 );
             }
-            let mut data = Vec::new();
-            io::stdin().read_to_end(&mut data).context("read stdin")?;
-            data
         }
-    };
-
-    if data.len() > MAX_DATA_SIZE {
-        bail!("the data size is too large, we limit the maximum size to 10 MiB to ensure TUI performance, you should try to reduce the read size");
     }
 
-    // To make sure the data is utf8 encoded.
-    let data = String::from_utf8(data).context("parse file utf8")?;
-
-    let tree = Tree::parse(&cfg, &data, content_type).context("parse file")?;
-
-    let mut app = App::new(&cfg, tree);
+    let input = Input {
+        path: args.path.clone(),
+        format_name,
+        content_type,
+        format,
+        encoding: args.encoding,
+        lenient: args.lenient,
+        huge,
+    };
 
-    if !cfg.header.disable {
-        let header_ctx = HeaderContext::new(args.path, content_type, data.len());
-        app.set_header(header_ctx);
+    if let Some(script) = args.script.as_ref() {
+        return run_script(&cfg, &input, &args, script);
     }
 
     let mut terminal = ui::start().context("start tui")?;
-    let result = app.show(&mut terminal).context("show tui");
+    let result = open(&cfg, &input, &args, &mut terminal);
 
     // Regardless of how the TUI app executes, we should always restore the terminal.
     // Otherwise, if the app encounters an error (such as a draw error), the user's terminal
@@ -157,6 +142,232 @@ fn run() -> Result<()> {
     result
 }
 
+/// Load the config with the command line args applied, it is also used to reload the
+/// config in the TUI.
+fn load_config(args: &CommandArgs) -> Result<Config> {
+    let mut cfg = if args.ignore_config {
+        Config::default()
+    } else {
+        Config::load(args.config.clone())?
+    };
+
+    if args.vertical {
+        cfg.layout.direction = LayoutDirection::Vertical;
+    }
+    if args.horizontal {
+        cfg.layout.direction = LayoutDirection::Horizontal;
+    }
+
+    if args.disable_header {
+        cfg.header.disable = true;
+    }
+
+    if let Some(format) = args.header_format.as_ref() {
+        cfg.header.format = format.clone();
+    }
+
+    if let Some(size) = args.size {
+        cfg.layout.tree_size = size;
+    }
+
+    cfg.parse().context("parse config")?;
+    Ok(cfg)
+}
+
+/// The data to load, resolved from the command line args.
+struct Input<'a> {
+    path: Option<String>,
+    format_name: String,
+    content_type: ContentType,
+    format: Option<&'a Format>,
+    encoding: Encoding,
+    lenient: bool,
+    /// Open the json file in huge-file mode, see [`Tree::parse_huge`].
+    huge: bool,
+}
+
+/// The data loaded in the loading thread.
+enum Loaded {
+    Tree(Box<Tree>, HeaderContext),
+    /// The data cannot be parsed, the parse error is shown with the data.
+    Invalid(anyhow::Error, String),
+}
+
+/// Load the data in another thread with the progress shown, and show it in the TUI.
+fn open(
+    cfg: &Config,
+    input: &Input,
+    args: &CommandArgs,
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+) -> Result<()> {
+    let progress = Progress::default();
+    let loaded = thread::scope(|scope| {
+        let loader = scope.spawn(|| load(cfg, input, &progress));
+
+        let source = input.path.clone().unwrap_or_else(|| String::from("stdin"));
+        let page = LoadingPage::new(cfg, source, &progress);
+        let shown = page.show(terminal, || loader.is_finished());
+        if !matches!(shown, Ok(true)) {
+            // The loader must stop before leaving the scope.
+            progress.cancel();
+        }
+        let loaded = loader
+            .join()
+            .unwrap_or_else(|err| panic::resume_unwind(err));
+        match shown {
+            Ok(true) => loaded.map(Some),
+            // The loading is cancelled by the user.
+            Ok(false) => Ok(None),
+            Err(err) => Err(err).context("show tui"),
+        }
+    })?;
+
+    match loaded {
+        None => Ok(()),
+        Some(Loaded::Tree(tree, header_ctx)) => {
+            let mut app = new_app(cfg, *tree, header_ctx);
+            if !args.ignore_config {
+                let path = Config::get_path(args.config.clone()).context("get config path")?;
+                let args = args.clone();
+                app.set_config_source(path, move || load_config(&args));
+            }
+            app.show(terminal).context("show tui")
+        }
+        Some(Loaded::Invalid(err, data)) => {
+            let parse_err = err.downcast_ref::<ParseError>().unwrap();
+            if args.open_error {
+                let mut page = ErrorPage::new(cfg, parse_err, &data);
+                return page.show(terminal).context("show tui");
+            }
+
+            Err(report_parse_error(err, &data, input))
+        }
+    }
+}
+
+/// Run the script against the app without a terminal, and write the dumped screens.
+fn run_script(cfg: &Config, input: &Input, args: &CommandArgs, path: &str) -> Result<()> {
+    let script = fs::read_to_string(path).context("read script file")?;
+    let script = Script::parse(&script).context("parse script")?;
+
+    let mut app = match load(cfg, input, &Progress::default())? {
+        Loaded::Tree(tree, header_ctx) => new_app(cfg, *tree, header_ctx),
+        Loaded::Invalid(err, data) => return Err(report_parse_error(err, &data, input)),
+    };
+
+    let (width, height) = args.screen_size;
+    let opts = DumpOptions {
+        each_step: args.dump_each_step,
+        styles: args.dump_styles,
+    };
+    let screens = script
+        .run(&mut app, width, height, opts)
+        .context("run script")?;
+
+    match args.dump_screen.as_ref() {
+        Some(path) => fs::write(path, screens).context("write screen dump file"),
+        None => {
+            print!("{screens}");
+            Ok(())
+        }
+    }
+}
+
+fn new_app(cfg: &Config, tree: Tree, mut header_ctx: HeaderContext) -> App {
+    header_ctx.set_warnings(tree.warnings());
+    let mut app = App::new(cfg.clone(), tree);
+    app.set_header(header_ctx);
+    app
+}
+
+/// Returns the parse error with the source around the error position.
+fn report_parse_error(err: anyhow::Error, data: &str, input: &Input) -> anyhow::Error {
+    let parse_err = err.downcast_ref::<ParseError>().unwrap();
+    // The position is in the converted data for the external format.
+    let source = match (input.path.as_ref(), input.format) {
+        (Some(path), None) => path.clone(),
+        (Some(path), Some(_)) => format!("{path} (converted)"),
+        (None, _) => String::from("stdin"),
+    };
+    let report = parse_err.report(&source, data);
+    anyhow!("{:#}\n{report}", err.context("parse file"))
+}
+
+/// Read, convert and parse the data, this runs in the loading thread.
+fn load(cfg: &Config, input: &Input, progress: &Progress) -> Result<Loaded> {
+    if input.huge {
+        let file = File::open(input.path.as_ref().unwrap()).context("open file")?;
+        // SAFETY: The mapped data may change if the file is modified by another
+        // process, the same as other tools viewing large files, we never write it.
+        let data = unsafe { Mmap::map(&file) }.context("map file")?;
+        let size = data.len();
+        let tree = Tree::parse_huge(cfg, Arc::new(data), progress).context("parse file")?;
+        let content_type = format!("{}, huge file", input.format_name);
+        let header_ctx = HeaderContext::new(input.path.clone(), content_type, Encoding::Utf8, size);
+        return Ok(Loaded::Tree(Box::new(tree), header_ctx));
+    }
+
+    let data = match input.path.as_ref() {
+        Some(path) => {
+            let file = File::open(path).context("open file")?;
+            let size = file.metadata().context("get file metadata")?.len() as usize;
+            progress.set_total(size);
+            read_data(file, progress).context("read file")?
+        }
+        None => read_data(io::stdin(), progress).context("read stdin")?,
+    };
+    check_size(cfg, data.len())?;
+
+    let data_size = data.len();
+    let data = match input.format {
+        Some(format) => format
+            .convert(data, progress)
+            .with_context(|| format!("convert data with format '{}'", input.format_name))?,
+        None => data,
+    };
+    let (data, encoding) = input.encoding.decode(data).context("decode file")?;
+
+    match Tree::parse(cfg, &data, input.content_type, input.lenient, progress) {
+        Ok(tree) => {
+            let header_ctx = HeaderContext::new(
+                input.path.clone(),
+                input.format_name.clone(),
+                encoding,
+                data_size,
+            );
+            Ok(Loaded::Tree(Box::new(tree), header_ctx))
+        }
+        Err(err) if err.is::<ParseError>() => Ok(Loaded::Invalid(err, data)),
+        Err(err) => Err(err.context("parse file")),
+    }
+}
+
+/// Read all the data, the bytes read are reported to the progress.
+fn read_data(mut reader: impl Read, progress: &Progress) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut buf = vec![0; READ_BUFFER_SIZE];
+    loop {
+        progress.check()?;
+        let size = match reader.read(&mut buf) {
+            Ok(0) => return Ok(data),
+            Ok(size) => size,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        data.extend_from_slice(&buf[..size]);
+        progress.add_bytes(size);
+    }
+}
+
+fn check_size(cfg: &Config, size: usize) -> Result<()> {
+    if size > cfg.data.max_size {
+        let size = humansize::format_size(size, humansize::BINARY);
+        let max_size = humansize::format_size(cfg.data.max_size, humansize::BINARY);
+        bail!("the data size {size} exceeds the limit {max_size} to ensure TUI performance, only a json file can be opened in huge-file mode, you can raise `data.max_size` in config or reduce the read size");
+    }
+    Ok(())
+}
+
 fn main() {
     match run() {
         Ok(_) => {}
@@ -166,3 +377,44 @@ fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the data in chunks, and cancels the loading after the first chunk.
+    struct CancelReader<'a> {
+        data: &'a [u8],
+        progress: &'a Progress,
+    }
+
+    impl Read for CancelReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let size = self.data.len().min(buf.len()).min(2);
+            buf[..size].copy_from_slice(&self.data[..size]);
+            self.data = &self.data[size..];
+            self.progress.cancel();
+            Ok(size)
+        }
+    }
+
+    #[test]
+    fn test_read_data() {
+        let progress = Progress::default();
+        let data = read_data(&b"[1, 2]"[..], &progress).unwrap();
+        assert_eq!(data, b"[1, 2]");
+        assert_eq!(progress.bytes(), 6);
+    }
+
+    #[test]
+    fn test_read_data_cancelled() {
+        let progress = Progress::default();
+        let reader = CancelReader {
+            data: b"[1, 2]",
+            progress: &progress,
+        };
+        let err = read_data(reader, &progress).unwrap_err();
+        assert_eq!(err.to_string(), "loading is cancelled");
+        assert_eq!(progress.bytes(), 2);
+    }
+}
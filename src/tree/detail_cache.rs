@@ -0,0 +1,41 @@
+use std::collections::VecDeque;
+
+/// The rendered details of the recently selected items. The detail of a large array or
+/// object is expensive to serialize, but it is rendered on every frame while the item is
+/// selected, so the latest ones are kept here, the least recently used one is dropped
+/// when the cache is full.
+pub(super) struct DetailCache {
+    capacity: usize,
+    /// The cached details, the most recently used one is at the front.
+    entries: VecDeque<(String, String)>,
+}
+
+impl DetailCache {
+    pub(super) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub(super) fn get(&mut self, id: &str) -> Option<&str> {
+        let idx = self.entries.iter().position(|(key, _)| key == id)?;
+        let entry = self.entries.remove(idx).unwrap();
+        self.entries.push_front(entry);
+        self.entries.front().map(|(_, detail)| detail.as_str())
+    }
+
+    pub(super) fn insert(&mut self, id: String, detail: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_back();
+        }
+        self.entries.push_front((id, detail));
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
@@ -0,0 +1,627 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+
+use crate::tree::pointer::strip_pointer_prefix;
+use crate::tree::{
+    join_path, to_pointer, Annotations, ContentType, ObjectKeys, ParseError, Progress,
+};
+
+/// The data of a huge json file, usually memory-mapped.
+pub type HugeData = Arc<dyn AsRef<[u8]> + Send + Sync>;
+
+/// The json data too large to be parsed as a whole. The containers are indexed by their
+/// spans in the data, and parsed only when they are opened. A container not parsed yet
+/// is stored as an empty array or object in the tree value.
+#[derive(Clone)]
+pub(super) struct HugeSource {
+    data: HugeData,
+    /// The spans of the containers keyed by the item path.
+    index: HashMap<String, Span>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    start: usize,
+    end: usize,
+    /// The number of items or fields in the container.
+    len: usize,
+    parsed: bool,
+}
+
+/// The detail of a container larger than this is truncated to this size.
+const MAX_DETAIL_SIZE: usize = 1024 * 1024;
+
+/// The scanned bytes are reported to the progress every this size.
+const PROGRESS_STEP: usize = 1024 * 1024;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+
+impl HugeSource {
+    /// Parse the root value of the data, the nested containers are only checked for
+    /// the brackets and strings, and indexed.
+    pub(super) fn load(
+        data: HugeData,
+        annotations: &mut Annotations,
+        progress: &Progress,
+    ) -> Result<(Value, Self)> {
+        let mut source = Self {
+            data,
+            index: HashMap::new(),
+        };
+        let data = Arc::clone(&source.data);
+        let bytes = (*data).as_ref();
+        progress.set_total(bytes.len());
+
+        let start = if bytes.starts_with(UTF8_BOM) {
+            UTF8_BOM.len()
+        } else {
+            0
+        };
+        let start = skip_whitespace(bytes, start);
+        let (value, end) = match bytes.get(start) {
+            Some(b'{' | b'[') => {
+                source.parse_container(bytes, start, &[], annotations, progress)?
+            }
+            Some(_) => {
+                let (value, end) = parse_scalar(bytes, start)?;
+                if let Some(text) = raw_number(&value, &bytes[start..end]) {
+                    annotations.entry(String::new()).or_default().raw_number = Some(text);
+                }
+                (value, end)
+            }
+            None => return Err(syntax_error(bytes, start, "EOF while parsing a value")),
+        };
+        let end = skip_whitespace(bytes, end);
+        if end < bytes.len() {
+            return Err(syntax_error(bytes, end, "trailing characters"));
+        }
+        progress.set_bytes(end);
+        Ok((value, source))
+    }
+
+    /// Returns the number of items in the container `id`, if it is not parsed yet.
+    pub(super) fn len(&self, id: &str) -> Option<usize> {
+        let span = self.index.get(id)?;
+        if span.parsed {
+            return None;
+        }
+        Some(span.len)
+    }
+
+    /// Parse the container at `path` if it is not parsed yet, its nested containers are
+    /// indexed.
+    pub(super) fn load_container(
+        &mut self,
+        path: &[String],
+        annotations: &mut Annotations,
+    ) -> Option<Result<Value>> {
+        let span = self.index.get_mut(&to_pointer(path))?;
+        if span.parsed {
+            return None;
+        }
+        span.parsed = true;
+        let start = span.start;
+
+        let data = Arc::clone(&self.data);
+        let result = self
+            .parse_container(
+                (*data).as_ref(),
+                start,
+                path,
+                annotations,
+                &Progress::default(),
+            )
+            .map(|(value, _)| value)
+            .context("parse json");
+        Some(result)
+    }
+
+    /// Returns the text of the container `id` to be shown in the data block. It is
+    /// formatted from the source, since the nested containers may be not parsed yet, see
+    /// [`format_json`]. The container larger than [`MAX_DETAIL_SIZE`] is cut off at a
+    /// character boundary.
+    pub(super) fn render(&self, id: &str) -> Option<Result<String>> {
+        let span = self.index.get(id)?;
+        let bytes = &(*self.data).as_ref()[span.start..span.end];
+        let cut = bytes.len() > MAX_DETAIL_SIZE;
+        let bytes = if cut {
+            &bytes[..MAX_DETAIL_SIZE]
+        } else {
+            bytes
+        };
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) => text,
+            // The last character is cut in the middle.
+            Err(err) if cut && err.error_len().is_none() => {
+                std::str::from_utf8(&bytes[..err.valid_up_to()]).unwrap()
+            }
+            Err(err) => {
+                let err = anyhow::Error::new(err).context("invalid utf-8 data");
+                return Some(Err(err).with_context(|| format!("render item '{id}'")));
+            }
+        };
+
+        let mut detail = format_json(text);
+        if cut {
+            let rest = span.end - span.start - text.len();
+            let rest = humansize::format_size(rest, humansize::BINARY);
+            detail.push_str(&format!("\n... ({rest} more)"));
+        }
+        Some(Ok(detail))
+    }
+
+    /// Returns the source of the subtree at `id`, used by [`super::Tree::subtree`]. The
+    /// container `id` becomes the root, it can be loaded by [`HugeSource::load_container`]
+    /// with the empty path.
+    pub(super) fn subtree(&self, id: &str) -> Self {
+        let index = self
+            .index
+            .iter()
+            .filter_map(|(path, span)| {
+                let path = if path == id {
+                    ""
+                } else {
+                    strip_pointer_prefix(path, id)?
+                };
+                Some((path.to_string(), *span))
+            })
+            .collect();
+        Self {
+            data: Arc::clone(&self.data),
+            index,
+        }
+    }
+
+    /// Parse the items or fields of the container starting at `start`, the nested
+    /// containers are indexed rather than parsed. Returns the value and the end of the
+    /// container.
+    fn parse_container(
+        &mut self,
+        bytes: &[u8],
+        start: usize,
+        parent: &[String],
+        annotations: &mut Annotations,
+        progress: &Progress,
+    ) -> Result<(Value, usize)> {
+        let is_object = bytes[start] == b'{';
+        let close = if is_object { b'}' } else { b']' };
+        let mut items = Vec::new();
+        let mut fields = Map::new();
+        let mut keys = ObjectKeys::default();
+
+        let mut pos = skip_whitespace(bytes, start + 1);
+        let mut closed = bytes.get(pos) == Some(&close);
+        while !closed {
+            let name = if is_object {
+                if bytes.get(pos) != Some(&b'"') {
+                    return Err(syntax_error(bytes, pos, "key must be a string"));
+                }
+                let (key, end) = match parse_scalar(bytes, pos)? {
+                    (Value::String(key), end) => (key, end),
+                    _ => unreachable!(),
+                };
+                let key_obj = keys.insert(&key, None);
+                key_obj.annotate(parent, &key, annotations);
+
+                pos = skip_whitespace(bytes, end);
+                if bytes.get(pos) != Some(&b':') {
+                    return Err(syntax_error(bytes, pos, "expected `:`"));
+                }
+                pos = skip_whitespace(bytes, pos + 1);
+                key_obj.stored
+            } else {
+                items.len().to_string()
+            };
+
+            let (value, end) = match bytes.get(pos) {
+                Some(open @ (b'{' | b'[')) => {
+                    let (end, len) = scan_container(bytes, pos, progress)?;
+                    let path = join_path(parent, &name);
+                    self.index.insert(
+                        path,
+                        Span {
+                            start: pos,
+                            end,
+                            len,
+                            parsed: false,
+                        },
+                    );
+                    let value = if *open == b'{' {
+                        Value::Object(Map::new())
+                    } else {
+                        Value::Array(Vec::new())
+                    };
+                    (value, end)
+                }
+                Some(_) => {
+                    let (value, end) = parse_scalar(bytes, pos)?;
+                    if let Some(text) = raw_number(&value, &bytes[pos..end]) {
+                        let path = join_path(parent, &name);
+                        annotations.entry(path).or_default().raw_number = Some(text);
+                    }
+                    (value, end)
+                }
+                None => return Err(syntax_error(bytes, pos, "EOF while parsing a value")),
+            };
+            if is_object {
+                fields.insert(name, value);
+            } else {
+                items.push(value);
+            }
+            progress.check()?;
+            progress.add_nodes(1);
+            progress.set_bytes(end);
+
+            pos = skip_whitespace(bytes, end);
+            match bytes.get(pos) {
+                Some(b',') => pos = skip_whitespace(bytes, pos + 1),
+                Some(c) if *c == close => closed = true,
+                Some(_) => {
+                    let message = if is_object {
+                        "expected `,` or `}`"
+                    } else {
+                        "expected `,` or `]`"
+                    };
+                    return Err(syntax_error(bytes, pos, message));
+                }
+                None => return Err(syntax_error(bytes, pos, "EOF while parsing a value")),
+            }
+        }
+
+        let value = if is_object {
+            Value::Object(fields)
+        } else {
+            Value::Array(items)
+        };
+        Ok((value, pos + 1))
+    }
+}
+
+fn skip_whitespace(bytes: &[u8], mut pos: usize) -> usize {
+    while bytes.get(pos).is_some_and(u8::is_ascii_whitespace) {
+        pos += 1;
+    }
+    pos
+}
+
+/// Returns the end of the string starting at `pos`, after the closing quote.
+fn scan_string(bytes: &[u8], pos: usize) -> Result<usize> {
+    let mut idx = pos + 1;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'\\' => idx += 2,
+            b'"' => return Ok(idx + 1),
+            _ => idx += 1,
+        }
+    }
+    Err(syntax_error(
+        bytes,
+        bytes.len(),
+        "EOF while parsing a string",
+    ))
+}
+
+/// Returns the end of the container starting at `pos`, and the number of its items or
+/// fields. Only the brackets and strings are checked, the scalars are checked when the
+/// container is parsed.
+fn scan_container(bytes: &[u8], pos: usize, progress: &Progress) -> Result<(usize, usize)> {
+    let mut stack = vec![bytes[pos]];
+    let mut commas = 0;
+    let mut empty = true;
+    let mut idx = pos + 1;
+    let mut next_report = idx + PROGRESS_STEP;
+    while idx < bytes.len() {
+        if idx >= next_report {
+            progress.check()?;
+            progress.set_bytes(idx);
+            next_report = idx + PROGRESS_STEP;
+        }
+        let byte = bytes[idx];
+        match byte {
+            b'"' => {
+                empty &= stack.len() > 1;
+                idx = scan_string(bytes, idx)?;
+                continue;
+            }
+            b'{' | b'[' => {
+                empty &= stack.len() > 1;
+                stack.push(byte);
+            }
+            b'}' | b']' => {
+                let open = if byte == b'}' { b'{' } else { b'[' };
+                if stack.pop() != Some(open) {
+                    let message = format!("unexpected `{}`", byte as char);
+                    return Err(syntax_error(bytes, idx, &message));
+                }
+                if stack.is_empty() {
+                    let len = if empty { 0 } else { commas + 1 };
+                    return Ok((idx + 1, len));
+                }
+            }
+            b',' if stack.len() == 1 => commas += 1,
+            _ if byte.is_ascii_whitespace() => {}
+            _ => empty &= stack.len() > 1,
+        }
+        idx += 1;
+    }
+    Err(syntax_error(
+        bytes,
+        bytes.len(),
+        "EOF while parsing a value",
+    ))
+}
+
+/// Parse the string, number, bool or null at `pos`, returns the value and its end.
+fn parse_scalar(bytes: &[u8], pos: usize) -> Result<(Value, usize)> {
+    let end = if bytes[pos] == b'"' {
+        scan_string(bytes, pos)?
+    } else {
+        let len = bytes[pos..]
+            .iter()
+            .position(|byte| matches!(byte, b',' | b']' | b'}') || byte.is_ascii_whitespace())
+            .unwrap_or(bytes.len() - pos);
+        pos + len
+    };
+    match serde_json::from_slice(&bytes[pos..end]) {
+        Ok(value) => Ok((value, end)),
+        Err(err) => {
+            // The position in the message is relative to the scalar.
+            let message = err.to_string();
+            let message = match message.rsplit_once(" at line ") {
+                Some((message, _)) => message,
+                None => message.as_str(),
+            };
+            Err(syntax_error(
+                bytes,
+                pos + err.column().saturating_sub(1),
+                message,
+            ))
+        }
+    }
+}
+
+/// Returns the json text formatted like [`serde_json::to_string_pretty`]. The text is
+/// formatted token by token rather than parsed, so that the numbers and the duplicate
+/// keys are kept as they are, and the text cut off is also formatted.
+fn format_json(text: &str) -> String {
+    const INDENT: &str = "  ";
+
+    let bytes = text.as_bytes();
+    let mut formatted = String::with_capacity(text.len());
+    let mut depth: usize = 0;
+    let newline = |formatted: &mut String, depth: usize| {
+        formatted.push('\n');
+        formatted.push_str(&INDENT.repeat(depth));
+    };
+
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let byte = bytes[idx];
+        match byte {
+            b'{' | b'[' => {
+                let close = if byte == b'{' { b'}' } else { b']' };
+                let next = skip_whitespace(bytes, idx + 1);
+                formatted.push(byte as char);
+                if bytes.get(next) == Some(&close) {
+                    formatted.push(close as char);
+                    idx = next + 1;
+                    continue;
+                }
+                depth += 1;
+                newline(&mut formatted, depth);
+            }
+            b'}' | b']' => {
+                depth = depth.saturating_sub(1);
+                newline(&mut formatted, depth);
+                formatted.push(byte as char);
+            }
+            b',' => {
+                formatted.push(',');
+                newline(&mut formatted, depth);
+            }
+            b':' => formatted.push_str(": "),
+            _ if byte.is_ascii_whitespace() => {}
+            _ => {
+                let end = if byte == b'"' {
+                    scan_string(bytes, idx).unwrap_or(bytes.len())
+                } else {
+                    let len = bytes[idx..]
+                        .iter()
+                        .position(|byte| {
+                            matches!(byte, b'{' | b'[' | b'}' | b']' | b',' | b':' | b'"')
+                                || byte.is_ascii_whitespace()
+                        })
+                        .unwrap_or(bytes.len() - idx);
+                    idx + len
+                };
+                formatted.push_str(&text[idx..end]);
+                idx = end;
+                continue;
+            }
+        }
+        idx += 1;
+    }
+    formatted
+}
+
+/// Returns the text of the number scalar, if the json number cannot keep it, like `1.50`
+/// or a big integer.
+fn raw_number(value: &Value, text: &[u8]) -> Option<String> {
+    let Value::Number(num) = value else {
+        return None;
+    };
+    let text = String::from_utf8_lossy(text);
+    (num.to_string() != text).then(|| text.into_owned())
+}
+
+fn syntax_error(bytes: &[u8], offset: usize, message: &str) -> anyhow::Error {
+    let offset = offset.min(bytes.len());
+    let line_start = bytes[..offset]
+        .iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |idx| idx + 1);
+    let line = bytes[..line_start]
+        .iter()
+        .filter(|byte| **byte == b'\n')
+        .count();
+    let column = String::from_utf8_lossy(&bytes[line_start..offset])
+        .chars()
+        .count();
+    ParseError::new(ContentType::Json, message.to_string(), line, column).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::config::Config;
+    use crate::tree::Tree;
+
+    use super::*;
+
+    const DATA: &str = r#"{"a": {"b": [1, 2], "c": 1.50}, "d": [], "e": 1}"#;
+
+    fn load(data: &str) -> (Value, HugeSource, Annotations) {
+        let mut annotations = Annotations::new();
+        let data: HugeData = Arc::new(data.as_bytes().to_vec());
+        let (value, source) =
+            HugeSource::load(data, &mut annotations, &Progress::default()).unwrap();
+        (value, source, annotations)
+    }
+
+    fn path(id: &str) -> Vec<String> {
+        crate::tree::parse_pointer(id)
+    }
+
+    #[test]
+    fn test_load() {
+        let (value, source, _) = load(DATA);
+        assert_eq!(value, json!({"a": {}, "d": [], "e": 1}));
+        assert_eq!(source.len("/a"), Some(2));
+        assert_eq!(source.len("/d"), Some(0));
+        assert_eq!(source.len("/e"), None);
+        // The nested containers are indexed when their parent is parsed.
+        assert_eq!(source.len("/a/b"), None);
+
+        let (value, _, annotations) = load("  1.50 ");
+        assert_eq!(value, json!(1.5));
+        assert_eq!(annotations[""].raw_number.as_deref(), Some("1.50"));
+
+        let data: HugeData = Arc::new(br#"{"a": [1, 2}"#.to_vec());
+        let result = HugeSource::load(data, &mut Annotations::new(), &Progress::default());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_load_container() {
+        let (_, mut source, mut annotations) = load(DATA);
+        let value = source
+            .load_container(&path("/a"), &mut annotations)
+            .unwrap()
+            .unwrap();
+        assert_eq!(value, json!({"b": [], "c": 1.5}));
+        assert_eq!(annotations["/a/c"].raw_number.as_deref(), Some("1.50"));
+        assert_eq!(source.len("/a"), None);
+        assert_eq!(source.len("/a/b"), Some(2));
+        assert!(source
+            .load_container(&path("/a"), &mut annotations)
+            .is_none());
+
+        let value = source
+            .load_container(&path("/a/b"), &mut annotations)
+            .unwrap()
+            .unwrap();
+        assert_eq!(value, json!([1, 2]));
+    }
+
+    #[test]
+    fn test_load_container_error() {
+        // Only the brackets and strings are checked before the container is parsed.
+        let (_, mut source, mut annotations) = load(r#"{"a": [1, tru]}"#);
+        let result = source
+            .load_container(&path("/a"), &mut annotations)
+            .unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_subtree() {
+        let (_, source, _) = load(DATA);
+        let mut subtree = source.subtree("/a");
+        assert_eq!(subtree.len(""), Some(2));
+        let mut annotations = Annotations::new();
+        let value = subtree
+            .load_container(&[], &mut annotations)
+            .unwrap()
+            .unwrap();
+        assert_eq!(value, json!({"b": [], "c": 1.5}));
+        assert_eq!(annotations["/c"].raw_number.as_deref(), Some("1.50"));
+        assert_eq!(subtree.len("/b"), Some(2));
+    }
+
+    #[test]
+    fn test_tree_subtree() {
+        let mut cfg = Config::default();
+        cfg.parse().unwrap();
+        let data: HugeData = Arc::new(DATA.as_bytes().to_vec());
+        let tree = Tree::parse_huge(&cfg, data, &Progress::default()).unwrap();
+
+        // The container is not opened before the root is changed to it.
+        let subtree = tree.subtree(&cfg, "/a").unwrap();
+        assert_eq!(subtree.items.len(), 2);
+        assert_eq!(subtree.get_value("/c"), Some(&json!(1.5)));
+        assert_eq!(subtree.get_value("/b"), Some(&json!([])));
+    }
+
+    #[test]
+    fn test_render() {
+        let (_, source, _) = load(r#"{"a": {"b": [1, 1.50, {}], "b": "x\"]", "c": []}}"#);
+        let expected = r#"{
+  "b": [
+    1,
+    1.50,
+    {}
+  ],
+  "b": "x\"]",
+  "c": []
+}"#;
+        assert_eq!(source.render("/a").unwrap().unwrap(), expected);
+        assert!(source.render("/a/b").is_none());
+    }
+
+    #[test]
+    fn test_render_truncated() {
+        // The cut falls in the middle of a two-byte character.
+        let text = "é".repeat(MAX_DETAIL_SIZE);
+        let data = format!(r#"{{"a": ["x{text}"]}}"#);
+        let (_, source, _) = load(&data);
+
+        let detail = source.render("/a").unwrap().unwrap();
+        let (detail, marker) = detail.rsplit_once('\n').unwrap();
+        assert_eq!(
+            detail,
+            format!("[\n  \"x{}", "é".repeat(MAX_DETAIL_SIZE / 2 - 2))
+        );
+        assert_eq!(marker, "... (1.00 MiB more)");
+    }
+
+    #[test]
+    fn test_load_cancelled() {
+        let progress = Progress::default();
+        progress.cancel();
+        let data: HugeData = Arc::new(DATA.as_bytes().to_vec());
+        let err = HugeSource::load(data, &mut Annotations::new(), &progress)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "loading is cancelled");
+
+        let mut cfg = Config::default();
+        cfg.parse().unwrap();
+        let data: HugeData = Arc::new(DATA.as_bytes().to_vec());
+        let err = Tree::parse_huge(&cfg, data, &progress).err().unwrap();
+        assert_eq!(format!("{err:#}"), "parse json: loading is cancelled");
+    }
+}
@@ -1,34 +1,171 @@
+mod detail_cache;
+mod huge;
+mod parse_error;
 mod parse_json;
 mod parse_toml;
 mod parse_yaml;
+mod pointer;
+mod progress;
+mod search;
 
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::mem;
 
 use anyhow::{Context, Result};
 use clap::ValueEnum;
 use ratatui::style::Style;
 use ratatui::text::{Line, Span};
-use serde_json::Value;
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
 use tui_tree_widget::TreeItem;
 
 use crate::config::Config;
+use crate::encoding::Encoding;
 
-pub struct Tree<'a> {
-    pub items: Vec<TreeItem<'a, String>>,
-    pub details: HashMap<String, Detail>,
+use detail_cache::DetailCache;
+use huge::HugeSource;
+use pointer::{join_pointer, strip_pointer_prefix, unescape};
+use search::{Filter, SearchHit};
+
+pub use huge::HugeData;
+pub use parse_error::ParseError;
+pub use pointer::{parse_pointer, to_pointer};
+pub use progress::Progress;
+pub use search::{ItemPath, SearchMatches, SearchQuery, SearchTarget};
+
+pub struct Tree {
+    /// The items styled by the config, they can be built again with another config by
+    /// [`Tree::restyle`].
+    pub items: Vec<TreeItem<'static, String>>,
+    pub annotations: Annotations,
     pub content_type: ContentType,
+
+    /// The parsed document, the values of the items are looked up in it by path, see
+    /// [`Tree::get_value`].
+    root: Value,
+    /// The values of the expanded embedded items keyed by the item path, see
+    /// [`Tree::expand_embedded`].
+    embedded: HashMap<String, Value>,
+    /// The content types of the data embedded in the string items keyed by the item
+    /// path, `None` if there is none. The detection parses the whole string, so it is
+    /// only done once for each item, see [`ContentType::detect_embedded`].
+    embeddable: HashMap<String, Option<ContentType>>,
+    /// The items whose children are not built yet, see [`Tree::load_children`]. The
+    /// containers hidden by the filter are not built at all, they are kept here too, so
+    /// that they are not fully built when they are shown again.
+    unloaded: HashSet<String>,
+    details: DetailCache,
+    /// The source data in huge-file mode, see [`Tree::parse_huge`].
+    huge: Option<HugeSource>,
+    /// The items matched by the search keyed by the item path, see [`Tree::search`].
+    search_hits: HashMap<String, SearchHit>,
+    /// Only the items matching the filter are shown, see [`Tree::filter`].
+    filter: Option<Filter>,
+}
+
+/// The information of the source document that cannot be stored in the json value, such
+/// as the yaml anchors.
+#[derive(Debug, Clone, Default)]
+pub struct Annotation {
+    /// The anchor defined on the node, like `&base`.
+    pub anchor: Option<String>,
+    /// The anchor that the node refers to, like `*base`.
+    pub alias: Option<String>,
+    /// The field is merged in by the `<<` key, this is the anchor name of the merge
+    /// source, empty if the source is an inline mapping.
+    pub merged_from: Option<String>,
+    /// The path of the anchor that the alias or the merge source refers to, which is
+    /// the latest definition of the anchor before it, see [`Tree::get_anchor_path`].
+    pub anchor_path: Option<String>,
+    /// The explicit tag of the node, like `!Ref` or `!!binary`.
+    pub tag: Option<String>,
+    /// The original text of the number, if it cannot be stored verbatim in the json
+    /// number, like `0x1F` in yaml or `1_000` in toml.
+    pub raw_number: Option<String>,
+    /// The string node is a toml datetime, which has no counterpart in json value, this
+    /// is its kind.
+    pub datetime: Option<DatetimeKind>,
+    /// The node is a document in a multi-document yaml stream. Such a stream is shown as
+    /// a list of documents, rather than an array.
+    pub document: bool,
+    /// The field has the same key as a sibling field. All the occurrences are kept, the
+    /// later ones are stored under distinct keys, see [`ObjectKeys`].
+    pub duplicate: bool,
+    /// The key in the source, if the field is stored under a different key.
+    pub source_key: Option<String>,
+    /// The typed key of the field, if the key is not a string. Yaml allows any node as
+    /// a mapping key, like `1`, `true` or `[a, b]`, such a field is stored under the key
+    /// text.
+    pub key: Option<Value>,
+    /// The comments attached to the node in the source, the comment lines right above
+    /// it and the trailing comment on its line, without the comment markers.
+    pub comments: Vec<String>,
+    /// The string node has been expanded to a subtree, this is the content type of the
+    /// embedded data, see [`Tree::expand_embedded`].
+    pub embedded: Option<ContentType>,
+    /// The node stands for the content skipped in lenient mode, this is the parse error.
+    pub error: Option<String>,
+}
+
+impl Annotation {
+    /// Returns the annotation of the node moved under the pointer `prefix`, like a
+    /// document in a stream or an expanded embedded value.
+    fn with_prefix(mut self, prefix: &str) -> Self {
+        self.anchor_path = self.anchor_path.map(|path| format!("{prefix}{path}"));
+        self
+    }
+
+    /// Returns the annotation of the node in the subtree at `prefix`, the anchor out of
+    /// the subtree cannot be jumped to.
+    fn strip_prefix(mut self, prefix: &str) -> Self {
+        self.anchor_path = self
+            .anchor_path
+            .and_then(|path| Some(strip_pointer_prefix(&path, prefix)?.to_string()));
+        self
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct Detail {
-    pub value: String,
-    pub raw_value: Value,
+/// The kinds of toml datetime, see: <https://toml.io/en/v1.0.0#offset-date-time>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatetimeKind {
+    /// Like `1979-05-27T07:32:00Z`.
+    OffsetDatetime,
+    /// Like `1979-05-27T07:32:00`.
+    LocalDatetime,
+    /// Like `1979-05-27`.
+    LocalDate,
+    /// Like `07:32:00`.
+    LocalTime,
 }
 
-#[derive(Debug, Clone, Copy, ValueEnum)]
+impl DatetimeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::OffsetDatetime => "offset datetime",
+            Self::LocalDatetime => "local datetime",
+            Self::LocalDate => "local date",
+            Self::LocalTime => "local time",
+        }
+    }
+}
+
+/// The annotations keyed by the node pointer, like `/spec/containers/0`, see
+/// [`to_pointer`].
+pub type Annotations = HashMap<String, Annotation>;
+
+/// The key of the error node in an object, see [`Annotation::error`].
+const ERROR_KEY: &str = "<error>";
+
+/// The id of the item of a scalar root, the root container has no item.
+const ROOT_ID: &str = "/root";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ContentType {
     Json,
+    /// Json with `//` and `/* */` comments, like the vscode settings.
+    Jsonc,
     Toml,
     Yaml,
 }
@@ -39,237 +176,1253 @@ struct TreeItemValue<'a> {
 
     description: Cow<'static, str>,
 
-    detail: Detail,
-
-    children: Option<Vec<TreeItem<'a, String>>>,
+    /// The value is a non-empty array or object.
+    has_children: bool,
 }
 
-impl<'a> Tree<'a> {
-    pub fn from_value(cfg: &'a Config, value: Value, content_type: ContentType) -> Result<Self> {
-        let mut details: HashMap<String, Detail> = HashMap::new();
+/// The number of rendered details kept in [`Tree`].
+const DETAIL_CACHE_SIZE: usize = 32;
+
+impl Tree {
+    /// Build the tree of the value. The `annotations` keep what the value cannot store,
+    /// they can be empty for a value not parsed from a document. The `content_type`
+    /// decides how the details of the items are rendered.
+    pub fn from_value(
+        cfg: &Config,
+        value: Value,
+        annotations: Annotations,
+        content_type: ContentType,
+    ) -> Result<Self> {
+        let mut tree = Self::build(
+            value,
+            annotations,
+            HashMap::new(),
+            HashMap::new(),
+            None,
+            content_type,
+        );
+        tree.build_items(cfg, None, None);
+        Ok(tree)
+    }
+
+    /// Returns the tree without items, they are built by [`Tree::build_items`].
+    fn build(
+        value: Value,
+        annotations: Annotations,
+        embedded: HashMap<String, Value>,
+        embeddable: HashMap<String, Option<ContentType>>,
+        huge: Option<HugeSource>,
+        content_type: ContentType,
+    ) -> Self {
+        Self {
+            items: vec![],
+            annotations,
+            content_type,
+            root: value,
+            embedded,
+            embeddable,
+            unloaded: HashSet::new(),
+            details: DetailCache::new(DETAIL_CACHE_SIZE),
+            huge,
+            search_hits: HashMap::new(),
+            filter: None,
+        }
+    }
+
+    /// Build the items again with the config, used when the config is reloaded. The
+    /// items loaded before are loaded again, so the opened items stay opened.
+    pub fn restyle(&mut self, cfg: &Config) {
+        let unloaded = mem::take(&mut self.unloaded);
+        self.build_items(cfg, Some(&unloaded), None);
+    }
+
+    /// Build the items of the root. The building stops if the loading is cancelled, the
+    /// caller should check the `progress` then.
+    fn build_items(
+        &mut self,
+        cfg: &Config,
+        unloaded_before: Option<&HashSet<String>>,
+        progress: Option<&Progress>,
+    ) {
+        let mut builder = ItemBuilder {
+            cfg,
+            annotations: &self.annotations,
+            embedded: &self.embedded,
+            embeddable: &mut self.embeddable,
+            huge: self.huge.as_ref(),
+            unloaded: &mut self.unloaded,
+            unloaded_before,
+            search_hits: &self.search_hits,
+            filter: self.filter.as_ref(),
+            content_type: self.content_type,
+            progress,
+        };
 
         // The root value needs to be expanded directly, since we donot want to see a
         // `root` item in the tree.
-        let items: Vec<TreeItem<String>> = if let Value::Array(arr) = value {
-            let mut items = Vec::with_capacity(arr.len());
-            for (idx, value) in arr.into_iter().enumerate() {
-                let item = Self::parse_value(
-                    cfg,
-                    vec![],
-                    idx.to_string(),
-                    value,
-                    &mut details,
-                    content_type,
-                )?;
-                items.push(item);
+        self.items = match &self.root {
+            value @ (Value::Array(_) | Value::Object(_)) => builder.build_children(&[], value),
+            value => {
+                // The scalar root may be expanded as embedded data.
+                let value = self.embedded.get(ROOT_ID).unwrap_or(value);
+                vec![builder.build(&[], String::from("root"), value, false)]
             }
-            items
-        } else if let Value::Object(obj) = value {
-            let mut items = Vec::with_capacity(obj.len());
-            for (field, value) in obj {
-                let item =
-                    Self::parse_value(cfg, vec![], field, value, &mut details, content_type)?;
-                items.push(item);
+        };
+    }
+
+    /// Decode the raw data with the `encoding` and parse it to tree, use
+    /// [`Encoding::Auto`] to detect the encoding by BOM.
+    pub fn from_bytes(
+        cfg: &Config,
+        data: Vec<u8>,
+        content_type: ContentType,
+        encoding: Encoding,
+    ) -> Result<Self> {
+        let (data, _) = encoding.decode(data).context("decode data")?;
+        Self::parse(cfg, &data, content_type, false, &Progress::default())
+    }
+
+    /// Parse the data to tree. In `lenient` mode, the json and yaml data cut off or broken
+    /// in the middle are recovered as much as possible, the skipped content is shown as an
+    /// error node. The nodes parsed are reported to the `progress`, and the parsing stops
+    /// with an error if it is cancelled.
+    pub fn parse(
+        cfg: &Config,
+        data: &str,
+        content_type: ContentType,
+        lenient: bool,
+        progress: &Progress,
+    ) -> Result<Self> {
+        let (value, annotations) = content_type.parse(data, lenient, progress)?;
+        let mut tree = Self::build(
+            value,
+            annotations,
+            HashMap::new(),
+            HashMap::new(),
+            None,
+            content_type,
+        );
+        tree.build_items(cfg, None, Some(progress));
+        progress.check()?;
+        Ok(tree)
+    }
+
+    /// Parse the json data too large to be parsed as a whole, like a multi-GB export.
+    /// Only the root is parsed here, the nested containers are indexed by their spans in
+    /// the data, and parsed when they are opened.
+    pub fn parse_huge(cfg: &Config, data: HugeData, progress: &Progress) -> Result<Self> {
+        let mut annotations = Annotations::new();
+        let (value, huge) =
+            HugeSource::load(data, &mut annotations, progress).context("parse json")?;
+        let mut tree = Self::build(
+            value,
+            annotations,
+            HashMap::new(),
+            HashMap::new(),
+            Some(huge),
+            ContentType::Json,
+        );
+        tree.build_items(cfg, None, Some(progress));
+        progress.check()?;
+        Ok(tree)
+    }
+
+    /// Build a new tree whose root is the item `id`, used by the `change_root` action.
+    /// Returns `None` if the item is not expandable.
+    pub fn subtree(&self, cfg: &Config, id: &str) -> Option<Self> {
+        let value = self.get_value(id)?;
+        if !matches!(value, Value::Array(_) | Value::Object(_)) {
+            return None;
+        }
+        let content_type = self.get_content_type(id);
+
+        let mut annotations: Annotations = self
+            .annotations
+            .iter()
+            .filter_map(|(path, annotation)| {
+                let path = strip_pointer_prefix(path, id)?;
+                Some((path.to_string(), annotation.clone().strip_prefix(id)))
+            })
+            .collect();
+        // The embedded data expanded in the subtree stays expanded.
+        let embedded: HashMap<String, Value> = self
+            .embedded
+            .iter()
+            .filter_map(|(path, value)| {
+                let path = strip_pointer_prefix(path, id)?;
+                Some((path.to_string(), value.clone()))
+            })
+            .collect();
+        let embeddable: HashMap<String, Option<ContentType>> = self
+            .embeddable
+            .iter()
+            .filter_map(|(path, content_type)| {
+                let path = strip_pointer_prefix(path, id)?;
+                Some((path.to_string(), *content_type))
+            })
+            .collect();
+
+        // In huge-file mode, the container not opened yet is empty in the value, it is
+        // parsed as the root of the subtree.
+        let mut value = value.clone();
+        let mut huge = self.huge.as_ref().map(|huge| huge.subtree(id));
+        let loaded = huge
+            .as_mut()
+            .and_then(|huge| huge.load_container(&[], &mut annotations));
+        match loaded {
+            Some(Ok(loaded)) => value = loaded,
+            Some(Err(err)) => {
+                annotations.entry(String::new()).or_default().error = Some(format!("{err:#}"));
             }
-            items
-        } else {
-            vec![Self::parse_value(
-                cfg,
-                vec![],
-                String::from("root"),
-                value,
-                &mut details,
-                content_type,
-            )?]
+            None => {}
+        }
+
+        let mut tree = Self::build(value, annotations, embedded, embeddable, huge, content_type);
+        tree.build_items(cfg, None, None);
+        Some(tree)
+    }
+
+    /// Expand the string item `id` which contains json or yaml data, like the json message
+    /// in a log entry, into a subtree. Returns `false` if the item cannot be expanded.
+    pub fn expand_embedded(&mut self, cfg: &Config, id: &str) -> Result<bool> {
+        let text = match self.get_value(id) {
+            Some(Value::String(text)) => text.clone(),
+            _ => return Ok(false),
         };
+        let detected = match self.embeddable.get(id) {
+            Some(detected) => *detected,
+            None => ContentType::detect_embedded(&text),
+        };
+        let content_type = match detected {
+            Some(content_type) => content_type,
+            None => return Ok(false),
+        };
+
+        let (value, annotations) = content_type
+            .parse(&text, false, &Progress::default())
+            .with_context(|| format!("parse embedded {} in '{id}'", content_type.name()))?;
+        for (path, annotation) in annotations {
+            // The annotation of the embedded document itself is not kept, the item has its
+            // own annotation.
+            if !path.is_empty() {
+                self.annotations
+                    .insert(format!("{id}{path}"), annotation.with_prefix(id));
+            }
+        }
+        self.annotations.entry(id.to_string()).or_default().embedded = Some(content_type);
+        self.embedded.insert(id.to_string(), value);
+        // The annotations of the item are changed.
+        self.details.clear();
+
+        let path = parse_pointer(id);
+        // The ancestors may be unloaded, like when expanding again in a subtree.
+        for idx in 1..path.len() {
+            self.load_children(cfg, &path[..idx]);
+        }
+
+        // Replace the string item with the subtree.
+        self.rebuild_item(cfg, &path, false);
+        Ok(true)
+    }
+
+    /// Build the children of the item at `path` if they are not built yet. To keep the
+    /// startup fast for large data, the children are only built when the item is opened.
+    pub fn load_children(&mut self, cfg: &Config, path: &[String]) {
+        let id = to_pointer(path);
+        // The hidden item is not in the tree, like the anchor jumped to in a filter.
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !filter.contains(&id))
+        {
+            return;
+        }
+        if !self.unloaded.remove(&id) {
+            return;
+        }
 
-        Ok(Self {
-            items,
-            details,
+        // In huge-file mode, the container is parsed when it is opened. The data is not
+        // fully checked before, so the error is shown as an error node.
+        let loaded = self
+            .huge
+            .as_mut()
+            .and_then(|huge| huge.load_container(path, &mut self.annotations));
+        match loaded {
+            Some(Ok(value)) => {
+                if let Some(target) = find_value_mut(&mut self.root, path) {
+                    *target = value;
+                }
+            }
+            Some(Err(err)) => {
+                self.annotations.entry(id).or_default().error = Some(format!("{err:#}"));
+            }
+            None => {}
+        }
+        self.rebuild_item(cfg, path, true);
+    }
+
+    fn rebuild_item(&mut self, cfg: &Config, path: &[String], load: bool) {
+        let id = to_pointer(path);
+        let content_type = self.get_content_type(&id);
+        let value = match lookup_value(&self.root, &self.embedded, &id) {
+            Some(value) => value,
+            None => return,
+        };
+
+        let (name, parent) = path.split_last().unwrap();
+        let item = ItemBuilder {
+            cfg,
+            annotations: &self.annotations,
+            embedded: &self.embedded,
+            embeddable: &mut self.embeddable,
+            huge: self.huge.as_ref(),
+            unloaded: &mut self.unloaded,
+            unloaded_before: None,
+            search_hits: &self.search_hits,
+            filter: self.filter.as_ref(),
             content_type,
-        })
+            progress: None,
+        }
+        .build(parent, name.clone(), value, load);
+
+        let target = find_item_mut(&mut self.items, path).expect("the item must be in the tree");
+        *target = item;
     }
 
-    pub fn parse(cfg: &'a Config, data: &str, content_type: ContentType) -> Result<Self> {
-        let value = content_type.parse(data)?;
-        Self::from_value(cfg, value, content_type)
+    /// Returns the value of the item `id`.
+    pub fn get_value(&self, id: &str) -> Option<&Value> {
+        lookup_value(&self.root, &self.embedded, id)
     }
 
-    fn parse_value(
-        cfg: &'a Config,
-        parent: Vec<String>,
+    /// Returns the text of the item `id` to be shown in the data block. The text of an
+    /// array or object is the serialized value, it is rendered when the item is
+    /// selected, and cached for the recently selected items.
+    pub fn get_detail(&mut self, id: &str) -> Option<Result<String>> {
+        if let Some(detail) = self.details.get(id) {
+            return Some(Ok(detail.to_string()));
+        }
+
+        let detail = match self.render_detail(id)? {
+            Ok(detail) => detail,
+            Err(err) => return Some(Err(err)),
+        };
+        self.details.insert(id.to_string(), detail.clone());
+        Some(Ok(detail))
+    }
+
+    fn render_detail(&self, id: &str) -> Option<Result<String>> {
+        let value = self.get_value(id)?;
+        let annotation = self.annotations.get(id);
+        if let Some(error) = annotation.and_then(|annotation| annotation.error.as_ref()) {
+            return Some(Ok(error.clone()));
+        }
+        if let Some(result) = self.huge.as_ref().and_then(|huge| huge.render(id)) {
+            return Some(result);
+        }
+
+        let detail = match value {
+            Value::Null => String::new(),
+            Value::String(s) => s.clone(),
+            // Show the number as it is written in the source.
+            Value::Number(num) => annotation
+                .and_then(|annotation| annotation.raw_number.clone())
+                .unwrap_or_else(|| num.to_string()),
+            Value::Bool(b) => b.to_string(),
+            Value::Array(_) | Value::Object(_) => {
+                let result = self
+                    .get_content_type(id)
+                    .serialize(value, id, &self.annotations)
+                    .with_context(|| format!("serialize for item '{id}'"));
+                return Some(result);
+            }
+        };
+        Some(Ok(detail))
+    }
+
+    /// Returns the content type of the item `id`, which is different from the tree if the
+    /// item is in an expanded embedded subtree.
+    fn get_content_type(&self, id: &str) -> ContentType {
+        let mut path = id;
+        loop {
+            let embedded = self
+                .annotations
+                .get(path)
+                .and_then(|annotation| annotation.embedded);
+            if let Some(content_type) = embedded {
+                return content_type;
+            }
+            match path.rsplit_once('/') {
+                Some((parent, _)) => path = parent,
+                None => return self.content_type,
+            }
+        }
+    }
+
+    /// Returns the number of documents if the tree is a multi-document stream.
+    pub fn document_count(&self) -> Option<usize> {
+        let count = self
+            .annotations
+            .values()
+            .filter(|annotation| annotation.document)
+            .count();
+        if count == 0 {
+            return None;
+        }
+        Some(count)
+    }
+
+    /// Returns the comments of the item `id` with the comment markers, to be shown above
+    /// the value.
+    pub fn get_comments(&self, id: &str) -> Option<String> {
+        let annotation = self.annotations.get(id)?;
+        if annotation.comments.is_empty() {
+            return None;
+        }
+        let marker = self.get_content_type(id).comment_marker();
+        let lines: Vec<_> = annotation
+            .comments
+            .iter()
+            .map(|line| {
+                if line.is_empty() {
+                    marker.to_string()
+                } else {
+                    format!("{marker} {line}")
+                }
+            })
+            .collect();
+        Some(lines.join("\n"))
+    }
+
+    /// Returns the warnings found when parsing the data, to be shown in the header.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut duplicates: Vec<_> = self
+            .annotations
+            .iter()
+            .filter(|(_, annotation)| annotation.duplicate)
+            .map(|(path, annotation)| match annotation.source_key.as_ref() {
+                Some(key) => {
+                    let (parent, _) = path.rsplit_once('/').unwrap_or_default();
+                    join_pointer(parent, key)
+                }
+                None => path.clone(),
+            })
+            .collect();
+        duplicates.sort_unstable();
+        duplicates.dedup();
+
+        let mut errors: Vec<_> = self
+            .annotations
+            .iter()
+            .filter(|(_, annotation)| annotation.error.is_some())
+            .map(|(path, _)| {
+                if path.is_empty() {
+                    "root"
+                } else {
+                    path.as_str()
+                }
+            })
+            .collect();
+        errors.sort_unstable();
+
+        let mut warnings = Vec::new();
+        if !duplicates.is_empty() {
+            let word = if duplicates.len() > 1 { "keys" } else { "key" };
+            warnings.push(format!("duplicate {word}: {}", duplicates.join(", ")));
+        }
+        if !errors.is_empty() {
+            warnings.push(format!("content skipped at: {}", errors.join(", ")));
+        }
+        warnings
+    }
+
+    /// Returns the path of the anchor that the item `id` refers to, either by alias or by
+    /// merge key.
+    pub fn get_anchor_path(&self, id: &str) -> Option<Vec<String>> {
+        let annotation = self.annotations.get(id)?;
+        annotation.anchor_path.as_deref().map(parse_pointer)
+    }
+}
+
+/// Builds the tree items. An item whose children are not built yet has a placeholder
+/// child, so that it is still shown as expandable.
+struct ItemBuilder<'b> {
+    cfg: &'b Config,
+    annotations: &'b Annotations,
+    embedded: &'b HashMap<String, Value>,
+    embeddable: &'b mut HashMap<String, Option<ContentType>>,
+    huge: Option<&'b HugeSource>,
+    unloaded: &'b mut HashSet<String>,
+    /// The items not loaded before, when the items are built again by
+    /// [`Tree::restyle`]. The other items are loaded, rather than only the first level.
+    unloaded_before: Option<&'b HashSet<String>>,
+    search_hits: &'b HashMap<String, SearchHit>,
+    filter: Option<&'b Filter>,
+    content_type: ContentType,
+    /// The progress of loading, the building stops if the loading is cancelled.
+    progress: Option<&'b Progress>,
+}
+
+impl ItemBuilder<'_> {
+    /// Build the item of the value, the children are built only if `load` is true.
+    fn build(
+        &mut self,
+        parent: &[String],
         name: String,
-        value: Value,
-        details: &mut HashMap<String, Detail>,
-        content_type: ContentType,
-    ) -> Result<TreeItem<'a, String>> {
-        let value = TreeItemValue::parse(cfg, &parent, &name, value, details, content_type)?;
+        value: &Value,
+        load: bool,
+    ) -> TreeItem<'static, String> {
+        let cfg = self.cfg;
+        let annotations = self.annotations;
+        let path = join_path(parent, &name);
+        let annotation = annotations.get(&path);
+        // The expanded embedded data has its own content type.
+        let content_type = annotation
+            .and_then(|annotation| annotation.embedded)
+            .unwrap_or(self.content_type);
 
+        // The container not parsed yet in huge-file mode is empty in the value.
+        let len = self.huge.and_then(|huge| huge.len(&path));
         let TreeItemValue {
-            type_text,
-            type_style,
-            description,
-            detail,
-            children,
-        } = value;
-
-        let path = if parent.is_empty() {
-            name.clone()
-        } else {
-            format!("{}/{name}", parent.join("/"))
-        };
-        details.insert(path, detail);
+            mut type_text,
+            mut type_style,
+            mut description,
+            has_children,
+        } = TreeItemValue::parse(cfg, value, len, annotation);
 
-        let line = Line::from(vec![
-            Span::styled(name.clone(), cfg.colors.item.name.style),
+        if annotation.map(|annotation| annotation.document) == Some(true) {
+            type_text = cfg.types.doc.as_str();
+            type_style = cfg.colors.item.type_doc.style;
+            if let Some(label) = cfg.document.format_label(value) {
+                description = Cow::Owned(label);
+            }
+        }
+        if let Some(error) = annotation.and_then(|annotation| annotation.error.as_ref()) {
+            type_text = cfg.types.error.as_str();
+            type_style = cfg.colors.item.type_error.style;
+            description = Cow::Owned(error.clone());
+        }
+
+        // The duplicate fields are stored under distinct keys, but shown with the key in
+        // the source.
+        let display_name = annotation
+            .and_then(|annotation| annotation.source_key.clone())
+            .unwrap_or_else(|| name.clone());
+        let mut name_span = match annotation.and_then(|annotation| annotation.key.as_ref()) {
+            Some(key) => Span::styled(parse_yaml::key_text(key), key_style(cfg, key)),
+            None => Span::styled(display_name, cfg.colors.item.name.style),
+        };
+        // The items matching the filter are highlighted too.
+        let hit = self
+            .search_hits
+            .get(&path)
+            .or_else(|| self.filter.and_then(|filter| filter.hits.get(&path)))
+            .copied()
+            .unwrap_or_default();
+        if hit.key {
+            name_span = name_span.patch_style(cfg.colors.item.search_match.style);
+        }
+        let mut spans = vec![
+            name_span,
             Span::raw(" "),
             Span::styled(type_text.to_string(), type_style),
-            Span::raw(" "),
-            Span::styled(description.to_string(), cfg.colors.item.description.style),
-        ]);
+        ];
+        if let Some(tag) = annotation.and_then(|annotation| annotation.tag.as_ref()) {
+            let tag_style = cfg.colors.item.tag.get(tag).style;
+            spans.push(Span::raw(" "));
+            spans.push(Span::styled(tag.clone(), tag_style));
+        }
+        spans.push(Span::raw(" "));
+        let mut description_style = cfg.colors.item.description.style;
+        if hit.value {
+            description_style = description_style.patch(cfg.colors.item.search_match.style);
+        }
+        spans.push(Span::styled(description.to_string(), description_style));
+        if let Value::Number(num) = value {
+            let raw_number = annotation.and_then(|annotation| annotation.raw_number.as_deref());
+            let non_finite = raw_number.is_some_and(is_non_finite);
+            // The big number is stored as a double, only its text tells the precision.
+            let text = match raw_number.filter(|text| is_decimal(text)) {
+                Some(text) => Cow::Borrowed(text),
+                None => Cow::Owned(num.to_string()),
+            };
+            if non_finite || lose_precision(&text) {
+                spans.push(Span::styled(" ⚠", cfg.colors.item.warning.style));
+            }
+        }
+        if let Some(annotation) = annotation {
+            let anchor_style = cfg.colors.item.anchor.style;
+            if let Some(anchor) = annotation.anchor.as_ref() {
+                spans.push(Span::styled(format!(" &{anchor}"), anchor_style));
+            }
+            if let Some(alias) = annotation.alias.as_ref() {
+                spans.push(Span::styled(format!(" -> *{alias}"), anchor_style));
+            }
+            if let Some(source) = annotation.merged_from.as_ref() {
+                let text = if source.is_empty() {
+                    String::from(" (inherited)")
+                } else {
+                    format!(" (inherited from *{source})")
+                };
+                spans.push(Span::styled(text, anchor_style));
+            }
+            if annotation.duplicate {
+                spans.push(Span::styled(
+                    " ⚠ duplicate key",
+                    cfg.colors.item.warning.style,
+                ));
+            }
+            if let Some(embedded) = annotation.embedded {
+                spans.push(Span::styled(
+                    format!(" ⧉ {}", embedded.name()),
+                    cfg.colors.item.embedded.style,
+                ));
+            }
+            if !annotation.comments.is_empty() {
+                spans.push(Span::styled(
+                    format!(" {}", content_type.comment_marker()),
+                    cfg.colors.item.comment.style,
+                ));
+            }
+        }
+        if let Value::String(s) = value {
+            // The embedded data is expanded on demand, only mark it here.
+            let embedded = *self
+                .embeddable
+                .entry(path.clone())
+                .or_insert_with(|| ContentType::detect_embedded(s));
+            if let Some(embedded) = embedded {
+                spans.push(Span::styled(
+                    format!(" ⧉ {}", embedded.name()),
+                    cfg.colors.item.embedded.style,
+                ));
+            }
+        }
+        let line = Line::from(spans);
+
+        if !has_children {
+            TreeItem::new_leaf(name, line)
+        } else if load {
+            let mut item_path = parent.to_vec();
+            item_path.push(name.clone());
+            let parent_type = mem::replace(&mut self.content_type, content_type);
+            let children = self.build_children(&item_path, value);
+            self.content_type = parent_type;
+            TreeItem::new(name, line, children).unwrap()
+        } else {
+            self.unloaded.insert(path);
+            let placeholder = TreeItem::new_leaf(String::new(), "…");
+            TreeItem::new(name, line, vec![placeholder]).unwrap()
+        }
+    }
 
-        let item = match children {
-            Some(children) => TreeItem::new(name, line, children).unwrap(),
-            None => TreeItem::new_leaf(name, line),
+    /// Build the items of the array items or object fields, their children are not
+    /// built, unless they are loaded before [`Tree::restyle`]. The items hidden by the
+    /// filter are skipped.
+    fn build_children(
+        &mut self,
+        parent: &[String],
+        value: &Value,
+    ) -> Vec<TreeItem<'static, String>> {
+        let children: Vec<(String, &Value)> = match value {
+            Value::Array(arr) => arr
+                .iter()
+                .enumerate()
+                .map(|(idx, item)| (idx.to_string(), item))
+                .collect(),
+            Value::Object(obj) => obj
+                .iter()
+                .map(|(field, item)| (field.clone(), item))
+                .collect(),
+            _ => return vec![],
         };
-        Ok(item)
+        let progress = self.progress;
+        children
+            .into_iter()
+            .take_while(|_| progress.is_none_or(|progress| !progress.is_cancelled()))
+            .filter_map(|(name, item)| {
+                // The embedded data may be expanded before the item is built.
+                let path = join_path(parent, &name);
+                let item = self.embedded.get(&path).unwrap_or(item);
+                if self.filter.is_some_and(|filter| !filter.contains(&path)) {
+                    // The hidden container is built unloaded when it is shown again,
+                    // the opened ones are loaded by the tree state then.
+                    if matches!(item, Value::Array(_) | Value::Object(_)) {
+                        self.unloaded.insert(path);
+                    }
+                    return None;
+                }
+                let load = self
+                    .unloaded_before
+                    .is_some_and(|unloaded| !unloaded.contains(&path));
+                Some(self.build(parent, name, item, load))
+            })
+            .collect()
     }
 }
 
 impl ContentType {
-    fn parse(&self, data: &str) -> Result<Value> {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Jsonc => "jsonc",
+            Self::Toml => "toml",
+            Self::Yaml => "yaml",
+        }
+    }
+
+    /// Returns the content type of the json or yaml object or array embedded in the
+    /// string. The yaml data must have multiple lines, otherwise almost any string with a
+    /// colon would be a yaml mapping. The data is checked by the same loader that
+    /// expands it, so that a marked item can always be expanded.
+    fn detect_embedded(s: &str) -> Option<Self> {
+        let trimmed = s.trim();
+        let content_type = if trimmed.starts_with('{') || trimmed.starts_with('[') {
+            Self::Json
+        } else if trimmed.contains('\n') {
+            Self::Yaml
+        } else {
+            return None;
+        };
+        let (value, _) = content_type.parse(s, false, &Progress::default()).ok()?;
+        matches!(value, Value::Array(_) | Value::Object(_)).then_some(content_type)
+    }
+
+    fn parse(
+        &self,
+        data: &str,
+        lenient: bool,
+        progress: &Progress,
+    ) -> Result<(Value, Annotations)> {
         match self {
-            Self::Json => parse_json::parse(data),
-            Self::Toml => parse_toml::parse(data),
-            Self::Yaml => parse_yaml::parse(data),
+            Self::Json => parse_json::parse(data, lenient, false, progress),
+            Self::Jsonc => parse_json::parse(data, lenient, true, progress),
+            Self::Toml => parse_toml::parse(data, progress),
+            Self::Yaml => parse_yaml::parse(data, lenient, progress),
         }
     }
 
-    fn serialize(&self, value: &Value) -> Result<String> {
+    fn serialize(&self, value: &Value, path: &str, annotations: &Annotations) -> Result<String> {
         match self {
-            Self::Json => parse_json::to_string(value),
-            Self::Toml => parse_toml::to_string(value),
-            Self::Yaml => parse_yaml::to_string(value),
+            Self::Json | Self::Jsonc => parse_json::to_string(value, path, annotations),
+            Self::Toml => parse_toml::to_string(value, path, annotations),
+            Self::Yaml => parse_yaml::to_string(value, path, annotations),
+        }
+    }
+
+    fn comment_marker(&self) -> &'static str {
+        match self {
+            Self::Json | Self::Jsonc => "//",
+            Self::Toml | Self::Yaml => "#",
         }
     }
 }
 
 impl<'a> TreeItemValue<'a> {
+    /// Parse the item of the value, `len` overrides the number of items of an array or
+    /// object.
     fn parse(
         cfg: &'a Config,
-        parent: &[String],
-        name: &String,
-        value: Value,
-        details: &mut HashMap<String, Detail>,
-        content_type: ContentType,
-    ) -> Result<Self> {
-        let raw_value = value.clone();
+        value: &Value,
+        len: Option<usize>,
+        annotation: Option<&Annotation>,
+    ) -> Self {
         match value {
-            Value::Null => Ok(Self {
+            Value::Null => Self {
                 type_text: cfg.types.null.as_str(),
                 type_style: cfg.colors.item.type_null.style,
                 description: Cow::Borrowed("null"),
-                detail: Detail {
-                    value: String::new(),
-                    raw_value,
+                has_children: false,
+            },
+            Value::String(s) => match annotation.and_then(|annotation| annotation.datetime) {
+                // The toml datetime is stored as its text.
+                Some(kind) => Self {
+                    type_text: cfg.types.datetime.as_str(),
+                    type_style: cfg.colors.item.type_datetime.style,
+                    description: Cow::Owned(format!("= {s} ({})", kind.name())),
+                    has_children: false,
                 },
-                children: None,
-            }),
-            Value::String(s) => Ok(Self {
-                type_text: cfg.types.str.as_str(),
-                type_style: cfg.colors.item.type_str.style,
-                description: Cow::Owned(format!("= {s:?}")),
-                detail: Detail {
-                    value: s,
-                    raw_value,
+                None => Self {
+                    type_text: cfg.types.str.as_str(),
+                    type_style: cfg.colors.item.type_str.style,
+                    description: Cow::Owned(format!("= {s:?}")),
+                    has_children: false,
                 },
-                children: None,
-            }),
-            Value::Number(num) => Ok(Self {
-                type_text: cfg.types.num.as_str(),
-                type_style: cfg.colors.item.type_num.style,
-                description: Cow::Owned(format!("= {num}")),
-                detail: Detail {
-                    value: num.to_string(),
-                    raw_value,
-                },
-                children: None,
-            }),
-            Value::Bool(b) => Ok(Self {
+            },
+            Value::Number(num) => {
+                // Show the number as it is written in the source.
+                let text = annotation
+                    .and_then(|annotation| annotation.raw_number.clone())
+                    .unwrap_or_else(|| num.to_string());
+                Self {
+                    type_text: cfg.types.num.as_str(),
+                    type_style: cfg.colors.item.type_num.style,
+                    description: Cow::Owned(format!("= {text}")),
+                    has_children: false,
+                }
+            }
+            Value::Bool(b) => Self {
                 type_text: cfg.types.bool.as_str(),
                 type_style: cfg.colors.item.type_bool.style,
                 description: Cow::Owned(format!("= {b}")),
-                detail: Detail {
-                    value: b.to_string(),
-                    raw_value,
-                },
-                children: None,
-            }),
+                has_children: false,
+            },
             Value::Array(arr) => {
-                let detail = content_type
-                    .serialize(&Value::Array(arr.clone()))
-                    .with_context(|| {
-                        format!("serialize for array item '{}/{name}'", parent.join("/"))
-                    })?;
-
-                let word = if arr.len() > 1 { "items" } else { "item" };
-                let description = Cow::Owned(format!("[ {} {word} ]", arr.len()));
-                let mut children = Vec::with_capacity(arr.len());
-                for (idx, item) in arr.into_iter().enumerate() {
-                    let mut child_parent = parent.to_vec();
-                    child_parent.push(name.clone());
-
-                    let child = Tree::parse_value(
-                        cfg,
-                        child_parent,
-                        idx.to_string(),
-                        item,
-                        details,
-                        content_type,
-                    )?;
-                    children.push(child);
-                }
-                Ok(Self {
+                let len = len.unwrap_or(arr.len());
+                let word = if len > 1 { "items" } else { "item" };
+                Self {
                     type_text: cfg.types.arr.as_str(),
                     type_style: cfg.colors.item.type_arr.style,
-                    description,
-                    detail: Detail {
-                        value: detail,
-                        raw_value,
-                    },
-                    children: Some(children),
-                })
+                    description: Cow::Owned(format!("[ {len} {word} ]")),
+                    has_children: len > 0,
+                }
             }
             Value::Object(obj) => {
-                let detail = content_type
-                    .serialize(&Value::Object(obj.clone()))
-                    .with_context(|| {
-                        format!("serialize for object item '{}/{name}'", parent.join("/"))
-                    })?;
-
-                let word = if obj.len() > 1 { "fields" } else { "field" };
-                let description = Cow::Owned(format!("{{ {} {word} }}", obj.len()));
-
-                let mut children = Vec::with_capacity(obj.len());
-                for (field, item) in obj {
-                    let mut child_parent = parent.to_vec();
-                    child_parent.push(name.clone());
-
-                    let child =
-                        Tree::parse_value(cfg, child_parent, field, item, details, content_type)?;
-                    children.push(child);
-                }
-                Ok(Self {
+                let len = len.unwrap_or(obj.len());
+                let word = if len > 1 { "fields" } else { "field" };
+                Self {
                     type_text: cfg.types.obj.as_str(),
                     type_style: cfg.colors.item.type_obj.style,
-                    description,
-                    detail: Detail {
-                        value: detail,
-                        raw_value,
-                    },
-                    children: Some(children),
-                })
+                    description: Cow::Owned(format!("{{ {len} {word} }}")),
+                    has_children: len > 0,
+                }
+            }
+        }
+    }
+}
+
+/// Returns the value at the pointer `id` in the document, the expanded embedded values
+/// are used in place of their strings. The root value of a scalar document is the `root`
+/// item.
+fn lookup_value<'v>(
+    root: &'v Value,
+    embedded: &'v HashMap<String, Value>,
+    id: &str,
+) -> Option<&'v Value> {
+    let (mut value, mut end) = match root {
+        Value::Array(_) | Value::Object(_) => (root, 0),
+        // The scalar root is shown as the item `/root`, which may be expanded as
+        // embedded data.
+        _ => {
+            id.strip_prefix(ROOT_ID)?;
+            (embedded.get(ROOT_ID).unwrap_or(root), ROOT_ID.len())
+        }
+    };
+    if id.len() == end {
+        return Some(value);
+    }
+
+    for name in id[end..].strip_prefix('/')?.split('/') {
+        value = match value {
+            Value::Array(arr) => arr.get(name.parse::<usize>().ok()?)?,
+            Value::Object(obj) => obj.get(unescape(name).as_ref())?,
+            _ => return None,
+        };
+        end += name.len() + 1;
+        if let Some(expanded) = embedded.get(&id[..end]) {
+            value = expanded;
+        }
+    }
+    Some(value)
+}
+
+/// Returns the value at `path` in the document to be replaced, the embedded values are
+/// not looked up.
+fn find_value_mut<'v>(root: &'v mut Value, path: &[String]) -> Option<&'v mut Value> {
+    path.iter().try_fold(root, |value, name| match value {
+        Value::Array(arr) => arr.get_mut(name.parse::<usize>().ok()?),
+        Value::Object(obj) => obj.get_mut(name),
+        _ => None,
+    })
+}
+
+/// Returns the item at `path` in the items.
+fn find_item_mut<'i>(
+    items: &'i mut [TreeItem<'static, String>],
+    path: &[String],
+) -> Option<&'i mut TreeItem<'static, String>> {
+    let (first, rest) = path.split_first()?;
+    let mut item = items.iter_mut().find(|item| item.identifier() == first)?;
+    for name in rest {
+        let idx = item
+            .children()
+            .iter()
+            .position(|child| child.identifier() == name)?;
+        item = item.child_mut(idx)?;
+    }
+    Some(item)
+}
+
+/// Returns the pointer of the child `name` of the item at `parent`, see [`to_pointer`].
+fn join_path(parent: &[String], name: &str) -> String {
+    join_pointer(&to_pointer(parent), name)
+}
+
+/// A comment in the source, used to attach the comments to the nodes by lines.
+struct Comment {
+    /// The comment text without the comment markers, a block comment may have multiple
+    /// lines.
+    lines: Vec<String>,
+    /// The first line of the comment, the comments are keyed by the last line.
+    start: usize,
+    /// There is code before the comment on its first line.
+    trailing: bool,
+}
+
+/// The comments keyed by the last line (0-based) of each comment.
+type Comments = HashMap<usize, Comment>;
+
+/// Attach the comments to the nodes, `nodes` are the first line and path of the nodes in
+/// document order. A node gets the comment lines right above it and the trailing comment
+/// on its line. If multiple nodes start on the same line (like the items of a flow
+/// sequence), only the first one gets the comments. A node spanning multiple lines can
+/// be listed again with its last line, to get the trailing comment there.
+fn attach_comments(comments: &Comments, nodes: &[(usize, String)], annotations: &mut Annotations) {
+    let mut attached = HashSet::new();
+    for (line, path) in nodes {
+        if path.is_empty() || !attached.insert(*line) {
+            continue;
+        }
+
+        let mut leading = Vec::new();
+        let mut above = line.checked_sub(1);
+        while let Some(comment) = above.and_then(|above| comments.get(&above)) {
+            if comment.trailing {
+                break;
+            }
+            leading.push(comment);
+            above = comment.start.checked_sub(1);
+        }
+
+        let mut lines: Vec<String> = leading
+            .into_iter()
+            .rev()
+            .flat_map(|comment| comment.lines.iter().cloned())
+            .collect();
+        if let Some(comment) = comments.get(line) {
+            if comment.trailing && comment.start == *line {
+                lines.extend(comment.lines.iter().cloned());
+            }
+        }
+        if !lines.is_empty() {
+            annotations
+                .entry(path.clone())
+                .or_default()
+                .comments
+                .extend(lines);
+        }
+    }
+}
+
+/// The style of a typed key, which is the same as the type of the key.
+fn key_style(cfg: &Config, key: &Value) -> Style {
+    let colors = &cfg.colors.item;
+    match key {
+        Value::Null => colors.type_null.style,
+        Value::Bool(_) => colors.type_bool.style,
+        Value::Number(_) => colors.type_num.style,
+        Value::String(_) => colors.name.style,
+        Value::Array(_) => colors.type_arr.style,
+        Value::Object(_) => colors.type_obj.style,
+    }
+}
+
+/// The keys of an object being loaded. The json object cannot hold duplicate keys, so
+/// the later occurrences are stored under distinct keys, like `name~2`, `name~3`.
+#[derive(Default)]
+struct ObjectKeys {
+    stored: HashSet<String>,
+    /// The source key to the stored key of its first occurrence. The source key is in
+    /// json text, so that the string key `"1"` and the number key `1` are different.
+    sources: HashMap<String, String>,
+}
+
+/// The key of a field to be stored in the object, returned by [`ObjectKeys::insert`].
+struct ObjectKey {
+    /// The key to store the field.
+    stored: String,
+    /// The stored key of the first field with the same source key, if the field is a
+    /// duplicate.
+    first: Option<String>,
+    /// The typed key, see [`Annotation::key`].
+    typed: Option<Value>,
+}
+
+impl ObjectKeys {
+    /// Insert the field with `key`, `typed` is the typed key if the key is not a string.
+    fn insert(&mut self, key: &str, typed: Option<Value>) -> ObjectKey {
+        let stored = if self.stored.contains(key) {
+            (2..)
+                .map(|idx| format!("{key}~{idx}"))
+                .find(|stored| !self.stored.contains(stored))
+                .unwrap()
+        } else {
+            key.to_string()
+        };
+        self.stored.insert(stored.clone());
+
+        let source = match typed.as_ref() {
+            Some(typed) => typed.to_string(),
+            None => Value::String(key.to_string()).to_string(),
+        };
+        let first = match self.sources.get(&source) {
+            Some(first) => Some(first.clone()),
+            None => {
+                self.sources.insert(source, stored.clone());
+                None
+            }
+        };
+        ObjectKey {
+            stored,
+            first,
+            typed,
+        }
+    }
+
+    /// Returns whether a field is stored under `key`.
+    fn contains(&self, key: &str) -> bool {
+        self.stored.contains(key)
+    }
+}
+
+impl ObjectKey {
+    /// Record the source key, the typed key and the duplicate mark of the field in the
+    /// annotations, `parent` is the path of the object.
+    fn annotate(&self, parent: &[String], source_key: &str, annotations: &mut Annotations) {
+        let stored_path = join_path(parent, &self.stored);
+        if self.stored != source_key {
+            annotations
+                .entry(stored_path.clone())
+                .or_default()
+                .source_key = Some(source_key.to_string());
+        }
+        if let Some(typed) = self.typed.as_ref() {
+            annotations.entry(stored_path.clone()).or_default().key = Some(typed.clone());
+        }
+        if let Some(first) = self.first.as_ref() {
+            annotations
+                .entry(join_path(parent, first))
+                .or_default()
+                .duplicate = true;
+            annotations.entry(stored_path).or_default().duplicate = true;
+        }
+    }
+}
+
+/// Returns whether the number would lose precision when it is parsed as a double, like
+/// what `JSON.parse` does in JavaScript.
+fn lose_precision(text: &str) -> bool {
+    const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+    if !text.contains(['.', 'e', 'E']) {
+        let digits = text.trim_start_matches(['-', '+']).trim_start_matches('0');
+        return match digits.parse::<u64>() {
+            Ok(n) => n > MAX_SAFE_INTEGER,
+            Err(_) => !digits.is_empty(),
+        };
+    }
+
+    let float: f64 = match text.parse() {
+        Ok(float) => float,
+        Err(_) => return false,
+    };
+    if float.is_infinite() {
+        return true;
+    }
+
+    // The shortest representation of the double keeps the same significant digits if
+    // nothing is lost.
+    significant_digits(text) != significant_digits(&format!("{float:e}"))
+}
+
+/// Returns the json number standing in for the infinity or nan, which json cannot
+/// represent. The infinity is stored as the largest double, and nan is stored as zero.
+/// The original text should be kept in [`Annotation::raw_number`].
+fn non_finite_number(float: f64) -> Number {
+    let float = if float.is_nan() {
+        0.0
+    } else {
+        f64::MAX.copysign(float)
+    };
+    Number::from_f64(float).expect("the number must be finite")
+}
+
+/// Returns whether the original text of a number is written in decimal, like `+12`,
+/// `.5` or `1E5`, rather than `0x1F` or `1_000`.
+fn is_decimal(text: &str) -> bool {
+    let digits = text.trim_start_matches(['-', '+']);
+    digits.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+        && digits
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '-' | '+'))
+}
+
+/// Returns whether the original text of a number is an infinity or nan, like `.inf` in
+/// yaml or `-nan` in toml.
+fn is_non_finite(text: &str) -> bool {
+    let text = text.trim_start_matches(['-', '+']).trim_start_matches('.');
+    text.eq_ignore_ascii_case("inf") || text.eq_ignore_ascii_case("nan")
+}
+
+/// Returns the significant digits and the exponent of the first digit of a decimal number
+/// text, so that `1.10`, `1.1` and `0.011e2` are all equal.
+fn significant_digits(text: &str) -> (String, i64) {
+    let text = text.trim_start_matches(['-', '+']);
+    let (mantissa, exp) = match text.split_once(['e', 'E']) {
+        Some((mantissa, exp)) => (mantissa, exp.parse::<i64>().unwrap_or_default()),
+        None => (text, 0),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+    let digits = format!("{int}{frac}");
+    let trimmed = digits.trim_start_matches('0');
+    let leading_zeros = (digits.len() - trimmed.len()) as i64;
+    let digits = trimmed.trim_end_matches('0');
+    if digits.is_empty() {
+        return (String::new(), 0);
+    }
+
+    let exp = exp + int.len() as i64 - leading_zeros - 1;
+    (digits.to_string(), exp)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn new_config() -> Config {
+        let mut cfg = Config::default();
+        cfg.parse().unwrap();
+        cfg
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let cfg = new_config();
+        let data: Vec<u8> = r#"{"a": "é"}"#.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let tree = Tree::from_bytes(&cfg, data, ContentType::Json, Encoding::Utf16be).unwrap();
+        assert_eq!(tree.get_value("/a"), Some(&json!("é")));
+
+        let data = b"{\"a\": \"\xE9\"}".to_vec();
+        let tree = Tree::from_bytes(&cfg, data, ContentType::Json, Encoding::Latin1).unwrap();
+        assert_eq!(tree.get_value("/a"), Some(&json!("é")));
+
+        let data = b"\xEF\xBB\xBF{}".to_vec();
+        let err = Tree::from_bytes(&cfg, data, ContentType::Json, Encoding::Latin1)
+            .err()
+            .unwrap();
+        assert_eq!(
+            format!("{err:#}"),
+            "decode data: the data has a utf-8 BOM, but the encoding is specified as latin1"
+        );
+    }
+
+    #[test]
+    fn test_parse_cancelled() {
+        let cfg = new_config();
+        let progress = Progress::default();
+        progress.cancel();
+        let cases = [
+            (ContentType::Json, r#"{"a": [1, 2]}"#),
+            (
+                ContentType::Jsonc,
+                r#"{"a": [1, 2] // comment
+}"#,
+            ),
+            (ContentType::Toml, "a = [1, 2]"),
+            (ContentType::Yaml, "a: [1, 2]"),
+        ];
+        for (content_type, data) in cases {
+            for lenient in [false, true] {
+                let err = Tree::parse(&cfg, data, content_type, lenient, &progress)
+                    .err()
+                    .unwrap();
+                assert!(
+                    format!("{err:#}").contains("loading is cancelled"),
+                    "{content_type:?}: {err:#}"
+                );
             }
         }
     }
+
+    #[test]
+    fn test_expand_embedded_root() {
+        let cfg = new_config();
+        let data = r#""{\"a\": [1, 2]}""#;
+        let data = data.as_bytes().to_vec();
+        let mut tree = Tree::from_bytes(&cfg, data, ContentType::Json, Encoding::Auto).unwrap();
+        assert_eq!(tree.items.len(), 1);
+        assert!(tree.items[0].children().is_empty());
+
+        assert!(tree.expand_embedded(&cfg, "/root").unwrap());
+        assert_eq!(tree.get_value("/root"), Some(&json!({"a": [1, 2]})));
+        assert_eq!(tree.get_value("/root/a/1"), Some(&json!(2)));
+        assert_eq!(tree.get_value("/rootx"), None);
+        assert_eq!(tree.items.len(), 1);
+        assert_eq!(tree.items[0].children().len(), 1);
+
+        let texts: Vec<_> = tree.paths().into_iter().map(|path| path.text).collect();
+        assert_eq!(texts, vec!["root", "root/a", "root/a/0", "root/a/1"]);
+    }
+
+    #[test]
+    fn test_lose_precision() {
+        assert!(!lose_precision("0"));
+        assert!(!lose_precision("-12"));
+        assert!(!lose_precision("9007199254740991"));
+        assert!(lose_precision("9007199254740993"));
+        assert!(lose_precision("-9007199254740993"));
+        assert!(lose_precision("123456789012345678901234567890"));
+
+        assert!(!lose_precision("0.1"));
+        assert!(!lose_precision("1.50"));
+        assert!(!lose_precision("1.5e3"));
+        assert!(!lose_precision("0.30000000000000004"));
+        assert!(lose_precision("0.1000000000000000055511151231257827"));
+        assert!(lose_precision("1e999"));
+        assert!(lose_precision("-1e999"));
+    }
+
+    #[test]
+    fn test_significant_digits() {
+        let digits = |digits: &str, exp: i64| (digits.to_string(), exp);
+        assert_eq!(significant_digits("1.1"), digits("11", 0));
+        assert_eq!(significant_digits("1.10"), digits("11", 0));
+        assert_eq!(significant_digits("0.011e2"), digits("11", 0));
+        assert_eq!(significant_digits("123"), digits("123", 2));
+        assert_eq!(significant_digits("-0.05"), digits("5", -2));
+        assert_eq!(significant_digits("1.5E-3"), digits("15", -3));
+        assert_eq!(significant_digits("0.0"), digits("", 0));
+    }
+
+    #[test]
+    fn test_is_decimal() {
+        assert!(is_decimal("12"));
+        assert!(is_decimal("+12"));
+        assert!(is_decimal("-.5"));
+        assert!(is_decimal("1E5"));
+        assert!(!is_decimal("0x1F"));
+        assert!(!is_decimal("0o17"));
+        assert!(!is_decimal("1_000"));
+        assert!(!is_decimal(".inf"));
+    }
+
+    #[test]
+    fn test_non_finite_number() {
+        assert_eq!(non_finite_number(f64::INFINITY).as_f64(), Some(f64::MAX));
+        assert_eq!(
+            non_finite_number(f64::NEG_INFINITY).as_f64(),
+            Some(f64::MIN)
+        );
+        assert_eq!(non_finite_number(f64::NAN).as_f64(), Some(0.0));
+
+        assert!(is_non_finite(".inf"));
+        assert!(is_non_finite("-.Inf"));
+        assert!(is_non_finite("+inf"));
+        assert!(is_non_finite("nan"));
+        assert!(is_non_finite(".NaN"));
+        assert!(!is_non_finite("0x1F"));
+        assert!(!is_non_finite("1_000"));
+    }
 }
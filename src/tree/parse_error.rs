@@ -0,0 +1,155 @@
+use std::fmt;
+
+use crate::tree::ContentType;
+
+/// A syntax error in the data, with the position to show the source around it.
+#[derive(Debug)]
+pub struct ParseError {
+    pub content_type: ContentType,
+    pub message: String,
+    /// The line of the error, starting from 0.
+    pub line: usize,
+    /// The column of the error in chars, starting from 0.
+    pub column: usize,
+}
+
+impl ParseError {
+    /// The number of lines to show before and after the error line in the report.
+    const CONTEXT_LINES: usize = 2;
+
+    pub fn new(content_type: ContentType, message: String, line: usize, column: usize) -> Self {
+        Self {
+            content_type,
+            message,
+            line,
+            column,
+        }
+    }
+
+    /// Create the error at the byte `offset` in the data.
+    pub fn at_offset(
+        content_type: ContentType,
+        message: String,
+        data: &str,
+        offset: usize,
+    ) -> Self {
+        let mut offset = offset.min(data.len());
+        while !data.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let before = &data[..offset];
+        let line = before.matches('\n').count();
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        let column = before[line_start..].chars().count();
+        Self::new(content_type, message, line, column)
+    }
+
+    /// Returns the byte offset of the error in the data.
+    pub fn offset(&self, data: &str) -> usize {
+        let mut lines = data.split_inclusive('\n');
+        let line_start: usize = lines.by_ref().take(self.line).map(str::len).sum();
+        let column: usize = lines
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .take(self.column)
+            .map(char::len_utf8)
+            .sum();
+        line_start + column
+    }
+
+    /// Returns the hint for the common mistakes.
+    pub fn hint(&self, data: &str) -> Option<&'static str> {
+        let line = data.lines().nth(self.line).unwrap_or_default();
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let current = line.chars().nth(self.column);
+        match self.content_type {
+            ContentType::Yaml if indent.contains('\t') || self.message.contains("tab") => {
+                Some("yaml does not allow tabs for indentation, use spaces instead")
+            }
+            ContentType::Json if self.message.starts_with("expected `,`") => {
+                Some("a comma may be missing after the previous value")
+            }
+            ContentType::Json if self.message.starts_with("trailing comma") => {
+                Some("json does not allow a comma after the last item")
+            }
+            ContentType::Json if current == Some('/') => {
+                Some("json does not allow comments, parse the data as jsonc instead")
+            }
+            ContentType::Json if current == Some('\'') => {
+                Some("json strings must be quoted with double quotes")
+            }
+            ContentType::Json if self.message.starts_with("key must be a string") => {
+                Some("json object keys must be quoted with double quotes")
+            }
+            ContentType::Toml if self.message.contains("invalid string") => {
+                Some("toml strings must be quoted, like `name = \"value\"`")
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the error report with the source lines around the error, and a caret
+    /// under the offending char, to be printed to the terminal.
+    pub fn report(&self, source: &str, data: &str) -> String {
+        let (snippet, _) = self.render_source(data, Some(Self::CONTEXT_LINES));
+        let mut report = format!(
+            "  --> {source}:{}:{}\n{snippet}",
+            self.line + 1,
+            self.column + 1
+        );
+        if let Some(hint) = self.hint(data) {
+            report.push_str(&format!("\n  = hint: {hint}"));
+        }
+        report
+    }
+
+    /// Render the source lines with line numbers, and a caret line under the error line.
+    /// If `context` is `None`, all the lines are rendered. Returns the rendered text and
+    /// the row of the error line in it.
+    pub fn render_source(&self, data: &str, context: Option<usize>) -> (String, usize) {
+        let lines: Vec<_> = data.lines().collect();
+        // The error can be at the end of the data, like an unclosed object.
+        let error_line = self.line.min(lines.len().saturating_sub(1));
+        let (start, end) = match context {
+            Some(context) => (
+                error_line.saturating_sub(context),
+                (error_line + context + 1).min(lines.len()),
+            ),
+            None => (0, lines.len()),
+        };
+        let width = end.to_string().len();
+
+        let mut rendered = Vec::with_capacity(end - start + 1);
+        let mut row = 0;
+        for (idx, line) in lines.iter().enumerate().take(end).skip(start) {
+            // The tabs are expanded, so that the caret can be aligned.
+            let line = line.replace('\t', "    ");
+            rendered.push(format!("{:>width$} | {line}", idx + 1));
+            if idx == error_line {
+                row = rendered.len() - 1;
+                let prefix: String = lines[idx].chars().take(self.column).collect();
+                let offset = prefix.replace('\t', "    ").chars().count();
+                rendered.push(format!("{:>width$} | {:offset$}^", "", ""));
+            }
+        }
+        if lines.is_empty() {
+            rendered.push(format!("{:>width$} | ^", ""));
+        }
+        (rendered.join("\n"), row)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {} column {}",
+            self.message,
+            self.line + 1,
+            self.column + 1
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -1,12 +1,789 @@
+use std::borrow::Cow;
+use std::fmt;
+
 use anyhow::{Context, Result};
-use serde_json::Value;
+use serde::de::{DeserializeSeed, Error as _, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Deserializer, Serialize, Serializer};
+use serde_json::value::RawValue;
+use serde_json::{Map, Number, Value};
+
+use crate::tree::pointer::join_pointer;
+use crate::tree::{
+    attach_comments, join_path, to_pointer, Annotations, Comment, Comments, ContentType,
+    ObjectKeys, ParseError, Progress, ERROR_KEY,
+};
+
+/// In lenient mode, the error node is written to the recovered data as this string, and
+/// replaced with null after parsing.
+const ERROR_PLACEHOLDER: &str = "__otree_error__";
 
+/// Parse the json data. For `jsonc`, the comments are allowed and kept as annotations.
 #[inline(always)]
-pub fn parse(data: &str) -> Result<Value> {
-    serde_json::from_str(data).context("parse json")
+pub fn parse(
+    data: &str,
+    lenient: bool,
+    jsonc: bool,
+    progress: &Progress,
+) -> Result<(Value, Annotations)> {
+    match load(data, jsonc, progress) {
+        Err(err) if lenient => recover(data, jsonc, err, progress),
+        result => result,
+    }
+}
+
+fn load(data: &str, jsonc: bool, progress: &Progress) -> Result<(Value, Annotations)> {
+    let (data, comments) = prepare(data, jsonc);
+    let mut loader = Loader {
+        annotations: Annotations::new(),
+        path: vec![],
+        numbers: NumberTexts {
+            data: &data,
+            offset: 0,
+        },
+        progress,
+    };
+    let mut de = serde_json::Deserializer::from_str(&data);
+    let value = ValueSeed {
+        loader: &mut loader,
+    }
+    .deserialize(&mut de)
+    .map_err(|err| syntax_error(&data, err))
+    .context("parse json")?;
+    de.end()
+        .map_err(|err| syntax_error(&data, err))
+        .context("parse json")?;
+
+    if !comments.is_empty() {
+        let nodes = scan_nodes(&data);
+        attach_comments(&comments, &nodes, &mut loader.annotations);
+    }
+    Ok((value, loader.annotations))
 }
 
 #[inline(always)]
-pub fn to_string(value: &Value) -> Result<String> {
-    serde_json::to_string_pretty(value).context("serialize json")
+pub fn to_string(value: &Value, path: &str, annotations: &Annotations) -> Result<String> {
+    let value = Restored {
+        value,
+        path: path.to_string(),
+        annotations,
+    };
+    serde_json::to_string_pretty(&value).context("serialize json")
+}
+
+/// Recover the json data cut off or broken at the error. The content after the error is
+/// skipped, and the unterminated containers are closed, with an error node in the
+/// innermost one.
+fn recover(
+    data: &str,
+    jsonc: bool,
+    err: anyhow::Error,
+    progress: &Progress,
+) -> Result<(Value, Annotations)> {
+    let parse_err = match err.downcast_ref::<ParseError>() {
+        Some(parse_err) => parse_err,
+        None => return Err(err),
+    };
+    let (stripped, _) = prepare(data, jsonc);
+    let offset = parse_err.offset(&stripped);
+    let cut = match find_cut(&stripped[..offset]) {
+        Some(cut) => cut,
+        // Nothing can be recovered.
+        None => return Err(err),
+    };
+
+    // The comments are replaced with spaces in place, so the cut is also valid in the
+    // source, and the comments before it are kept.
+    let mut recovered = data[..cut.offset].to_string();
+    if let Some(container) = cut.stack.last() {
+        if cut.has_items {
+            recovered.push(',');
+        }
+        match cut.key {
+            // The value of the field is broken, the error node takes its key.
+            Some((start, end)) => recovered.push_str(&format!("{}:", &data[start..end])),
+            None if *container == b'{' => recovered.push_str(&format!("\"{ERROR_KEY}\":")),
+            None => {}
+        }
+        recovered.push_str(&format!("\"{ERROR_PLACEHOLDER}\""));
+    }
+    for container in cut.stack.iter().rev() {
+        recovered.push(if *container == b'{' { '}' } else { ']' });
+    }
+
+    let (mut value, mut annotations) =
+        load(&recovered, jsonc, progress).context("parse recovered json")?;
+
+    // The error node is always the last one in the innermost container.
+    let mut path = Vec::new();
+    let mut node = &mut value;
+    if !cut.stack.is_empty() {
+        loop {
+            node = match node {
+                Value::Array(arr) => {
+                    path.push((arr.len() - 1).to_string());
+                    arr.last_mut().unwrap()
+                }
+                Value::Object(obj) => {
+                    let (key, value) = obj.iter_mut().last().unwrap();
+                    path.push(key.clone());
+                    value
+                }
+                _ => break,
+            };
+        }
+        *node = Value::Null;
+    }
+    annotations.entry(to_pointer(&path)).or_default().error = Some(parse_err.to_string());
+
+    Ok((value, annotations))
+}
+
+/// The position to cut the broken json data, see [`find_cut`].
+struct Cut {
+    offset: usize,
+    /// The unterminated containers at the cut, `{` or `[`.
+    stack: Vec<u8>,
+    /// Whether the innermost container has items before the cut.
+    has_items: bool,
+    /// The key of the field whose value is broken after the cut, the range in the data.
+    key: Option<(usize, usize)>,
+}
+
+/// Returns the last position in the data where a value is completed or a container is
+/// opened, the data before it can be made valid by closing the containers. The data
+/// must have no comments.
+fn find_cut(data: &str) -> Option<Cut> {
+    let bytes = data.as_bytes();
+    let mut stack: Vec<u8> = Vec::new();
+    let mut cut = None;
+    // The last byte that is not whitespace, to tell the keys from string values.
+    let mut last = 0;
+    let mut key = None;
+
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let b = bytes[idx];
+        let end = match b {
+            b'{' | b'[' => {
+                stack.push(b);
+                cut = Some(Cut {
+                    offset: idx + 1,
+                    stack: stack.clone(),
+                    has_items: false,
+                    key: None,
+                });
+                last = b;
+                idx += 1;
+                continue;
+            }
+            b'}' | b']' => {
+                stack.pop();
+                idx + 1
+            }
+            b'"' => {
+                let mut end = idx + 1;
+                while end < bytes.len() && bytes[end] != b'"' {
+                    if bytes[end] == b'\\' {
+                        end += 1;
+                    }
+                    end += 1;
+                }
+                if end >= bytes.len() {
+                    // The string is cut off.
+                    break;
+                }
+                let is_key = stack.last() == Some(&b'{') && (last == b'{' || last == b',');
+                if is_key {
+                    key = Some((idx, end + 1));
+                    last = b'"';
+                    idx = end + 1;
+                    continue;
+                }
+                end + 1
+            }
+            b if b.is_ascii_whitespace() => {
+                idx += 1;
+                continue;
+            }
+            b',' | b':' => {
+                if let (b':', Some(cut)) = (b, cut.as_mut()) {
+                    cut.key = key.take();
+                }
+                last = b;
+                idx += 1;
+                continue;
+            }
+            _ => {
+                let mut end = idx;
+                while end < bytes.len() && !b",}] \t\r\n".contains(&bytes[end]) {
+                    end += 1;
+                }
+                if end >= bytes.len() {
+                    // The scalar may be cut off, like `tr` or `12` of `123`.
+                    break;
+                }
+                end
+            }
+        };
+
+        // A value is completed.
+        cut = Some(Cut {
+            offset: end,
+            stack: stack.clone(),
+            has_items: true,
+            key: None,
+        });
+        last = bytes[end - 1];
+        idx = end;
+    }
+    cut
+}
+
+/// Convert the serde_json error to [`ParseError`], to show the source around it. The
+/// comments are replaced with spaces, so the position is the same as in the source.
+fn syntax_error(data: &str, err: serde_json::Error) -> anyhow::Error {
+    if err.line() == 0 {
+        return err.into();
+    }
+    let suffix = format!(" at line {} column {}", err.line(), err.column());
+    let message = err.to_string();
+    let message = message
+        .strip_suffix(&suffix)
+        .unwrap_or(&message)
+        .to_string();
+
+    let line_start: usize = data
+        .split_inclusive('\n')
+        .take(err.line() - 1)
+        .map(str::len)
+        .sum();
+    let offset = line_start + err.column().saturating_sub(1);
+    ParseError::at_offset(ContentType::Json, message, data, offset).into()
+}
+
+/// The json loader. Different from deserializing to [`Value`] directly, the loader keeps
+/// all the fields with duplicate keys, rather than the last one only.
+struct Loader<'p> {
+    annotations: Annotations,
+    path: Vec<String>,
+    /// The visitor only gets the parsed numbers, their texts are scanned from the data.
+    numbers: NumberTexts<'p>,
+    progress: &'p Progress,
+}
+
+impl Loader<'_> {
+    /// The json number cannot keep the original text like `1.50`, `1E5` or a big
+    /// integer, such text is kept in the annotation.
+    fn load_number(&mut self, num: Number) -> Value {
+        if let Some(text) = self.numbers.next() {
+            if num.to_string() != text {
+                self.annotations
+                    .entry(to_pointer(&self.path))
+                    .or_default()
+                    .raw_number = Some(text.to_string());
+            }
+        }
+        Value::Number(num)
+    }
+}
+
+/// The texts of the numbers in the json data, in the order they are written, which is
+/// also the order the visitor gets them.
+struct NumberTexts<'a> {
+    data: &'a str,
+    offset: usize,
+}
+
+impl<'a> Iterator for NumberTexts<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let bytes = self.data.as_bytes();
+        let mut idx = self.offset;
+        while idx < bytes.len() {
+            match bytes[idx] {
+                b'"' => {
+                    idx += 1;
+                    while idx < bytes.len() && bytes[idx] != b'"' {
+                        if bytes[idx] == b'\\' {
+                            idx += 1;
+                        }
+                        idx += 1;
+                    }
+                    idx += 1;
+                }
+                b'-' | b'0'..=b'9' => {
+                    let len = bytes[idx..]
+                        .iter()
+                        .position(|b| !matches!(b, b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E'))
+                        .unwrap_or(bytes.len() - idx);
+                    self.offset = idx + len;
+                    return Some(&self.data[idx..idx + len]);
+                }
+                _ => idx += 1,
+            }
+        }
+        self.offset = idx;
+        None
+    }
+}
+
+struct ValueSeed<'a, 'p> {
+    loader: &'a mut Loader<'p>,
+}
+
+impl<'de> DeserializeSeed<'de> for ValueSeed<'_, '_> {
+    type Value = Value;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ValueSeed<'_, '_> {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any valid JSON value")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Value, E> {
+        Ok(Value::Bool(value))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Value, E> {
+        Ok(self.loader.load_number(value.into()))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Value, E> {
+        Ok(self.loader.load_number(value.into()))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Value, E> {
+        Ok(Number::from_f64(value).map_or(Value::Null, |num| self.loader.load_number(num)))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Value, E> {
+        Ok(Value::String(value.to_string()))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<Value, E> {
+        Ok(Value::String(value))
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        self.loader.progress.check().map_err(A::Error::custom)?;
+        let mut arr = Vec::new();
+        loop {
+            self.loader.path.push(arr.len().to_string());
+            let value = seq.next_element_seed(ValueSeed {
+                loader: &mut *self.loader,
+            });
+            self.loader.path.pop();
+            match value? {
+                Some(value) => arr.push(value),
+                None => {
+                    self.loader.progress.add_nodes(arr.len());
+                    return Ok(Value::Array(arr));
+                }
+            }
+        }
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        self.loader.progress.check().map_err(A::Error::custom)?;
+        let mut obj = Map::new();
+        let mut keys = ObjectKeys::default();
+        while let Some(key) = map.next_key::<String>()? {
+            let object_key = keys.insert(&key, None);
+            object_key.annotate(&self.loader.path, &key, &mut self.loader.annotations);
+            let key = object_key.stored;
+
+            self.loader.path.push(key.clone());
+            let value = map.next_value_seed(ValueSeed {
+                loader: &mut *self.loader,
+            });
+            self.loader.path.pop();
+            obj.insert(key, value?);
+        }
+        self.loader.progress.add_nodes(obj.len());
+        Ok(Value::Object(obj))
+    }
+}
+
+/// Serialize the value with the duplicate keys restored, serde_json does not check the
+/// duplicate keys when writing a map.
+struct Restored<'a> {
+    value: &'a Value,
+    path: String,
+    annotations: &'a Annotations,
+}
+
+impl<'a> Restored<'a> {
+    fn child(&self, key: &str, value: &'a Value) -> Self {
+        Self {
+            value,
+            path: join_pointer(&self.path, key),
+            annotations: self.annotations,
+        }
+    }
+}
+
+impl Serialize for Restored<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.value {
+            Value::Array(arr) => {
+                let mut seq = serializer.serialize_seq(Some(arr.len()))?;
+                for (idx, item) in arr.iter().enumerate() {
+                    let child = self.child(&idx.to_string(), item);
+                    seq.serialize_element(&child)?;
+                }
+                seq.end()
+            }
+            Value::Object(obj) => {
+                let mut map = serializer.serialize_map(Some(obj.len()))?;
+                for (key, item) in obj {
+                    let child = self.child(key, item);
+                    let key = self
+                        .annotations
+                        .get(&child.path)
+                        .and_then(|annotation| annotation.source_key.as_ref())
+                        .unwrap_or(key);
+                    map.serialize_entry(key, &child)?;
+                }
+                map.end()
+            }
+            Value::Number(num) => {
+                // The number is written as it is in the source.
+                let raw = self
+                    .annotations
+                    .get(&self.path)
+                    .and_then(|annotation| annotation.raw_number.clone())
+                    .and_then(|text| RawValue::from_string(text).ok());
+                match raw {
+                    Some(raw) => raw.serialize(serializer),
+                    None => num.serialize(serializer),
+                }
+            }
+            value => value.serialize(serializer),
+        }
+    }
+}
+
+/// Returns the data to be parsed as json, the comments of `jsonc` are stripped by
+/// [`strip_comments`].
+fn prepare(data: &str, jsonc: bool) -> (Cow<'_, str>, Comments) {
+    if !jsonc {
+        return (Cow::Borrowed(data), Comments::new());
+    }
+    let (stripped, comments) = strip_comments(data);
+    (Cow::Owned(stripped), comments)
+}
+
+/// Replace the `//` and `/* */` comments with spaces, so that the data can be parsed as
+/// json, and the positions of the tokens are not changed.
+fn strip_comments(data: &str) -> (String, Comments) {
+    let bytes = data.as_bytes();
+    let mut stripped = bytes.to_vec();
+    let mut comments = Comments::new();
+
+    let mut line = 0;
+    let mut has_code = false;
+    let mut in_string = false;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let b = bytes[idx];
+        if in_string {
+            match b {
+                b'\\' => idx += 1,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            idx += 1;
+            continue;
+        }
+
+        let (end, text) = match (b, bytes.get(idx + 1)) {
+            (b'/', Some(b'/')) => {
+                let end = data[idx..].find('\n').map_or(data.len(), |end| idx + end);
+                (end, &data[idx + 2..end])
+            }
+            (b'/', Some(b'*')) => match data[idx + 2..].find("*/") {
+                Some(end) => (idx + 2 + end + 2, &data[idx + 2..idx + 2 + end]),
+                // Let the json parser report the error.
+                None => break,
+            },
+            _ => {
+                match b {
+                    b'"' => in_string = true,
+                    b'\n' => {
+                        line += 1;
+                        has_code = false;
+                        idx += 1;
+                        continue;
+                    }
+                    _ => {}
+                }
+                if !b.is_ascii_whitespace() {
+                    has_code = true;
+                }
+                idx += 1;
+                continue;
+            }
+        };
+
+        let start = line;
+        for b in stripped[idx..end].iter_mut() {
+            if *b == b'\n' {
+                line += 1;
+            } else {
+                *b = b' ';
+            }
+        }
+        let mut lines: Vec<_> = text
+            .lines()
+            .map(|text| {
+                // The block comment lines may start with `*`.
+                let text = text.trim();
+                text.strip_prefix('*').unwrap_or(text).trim().to_string()
+            })
+            .collect();
+        // Such as the `/*` and `*/` lines of a block comment.
+        while lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        if let Some(pos) = lines.iter().position(|line| !line.is_empty()) {
+            lines.drain(..pos);
+        }
+        let comment = comments.entry(line).or_insert_with(|| Comment {
+            lines: vec![],
+            start,
+            trailing: has_code,
+        });
+        comment.lines.extend(lines);
+        idx = end;
+    }
+
+    // The comments start and end at ascii chars, so all the bytes of the multi-byte chars
+    // in them are replaced, and the data is still valid utf8.
+    let stripped = String::from_utf8(stripped).expect("stripped json must be valid utf8");
+    (stripped, comments)
+}
+
+/// A container being scanned by [`scan_nodes`].
+enum Container {
+    /// The keys of the object, and the key of the current field if its value is
+    /// expected.
+    Object(ObjectKeys, Option<String>),
+    /// The number of items in the array.
+    Array(usize),
+}
+
+/// Returns the first line and path of each node in the json data, see
+/// [`attach_comments`]. The data must be valid json without comments.
+fn scan_nodes(data: &str) -> Vec<(usize, String)> {
+    let bytes = data.as_bytes();
+    let mut nodes = Vec::new();
+    // The containers, and whether each of them has a name pushed to the path.
+    let mut stack: Vec<(Container, bool)> = Vec::new();
+    let mut path: Vec<String> = Vec::new();
+
+    let mut line = 0;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let b = bytes[idx];
+        match b {
+            b'\n' => line += 1,
+            b'}' | b']' => {
+                if let Some((_, true)) = stack.pop() {
+                    path.pop();
+                }
+            }
+            b'"' => {
+                let mut end = idx + 1;
+                while end < bytes.len() && bytes[end] != b'"' {
+                    if bytes[end] == b'\\' {
+                        end += 1;
+                    }
+                    end += 1;
+                }
+                let text = &data[idx..=end.min(bytes.len() - 1)];
+                idx = end;
+
+                if let Some((Container::Object(keys, key @ None), _)) = stack.last_mut() {
+                    let name: String = serde_json::from_str(text).unwrap_or_default();
+                    let stored = keys.insert(&name, None).stored;
+                    nodes.push((line, join_path(&path, &stored)));
+                    *key = Some(stored);
+                } else {
+                    value_start(&mut stack, &path, line, &mut nodes);
+                }
+            }
+            b'{' | b'[' => {
+                let name = value_start(&mut stack, &path, line, &mut nodes);
+                let container = if b == b'{' {
+                    Container::Object(ObjectKeys::default(), None)
+                } else {
+                    Container::Array(0)
+                };
+                stack.push((container, name.is_some()));
+                path.extend(name);
+            }
+            b if b.is_ascii_whitespace() || b == b',' || b == b':' => {}
+            _ => {
+                value_start(&mut stack, &path, line, &mut nodes);
+                while idx + 1 < bytes.len() && !b",}] \t\r\n".contains(&bytes[idx + 1]) {
+                    idx += 1;
+                }
+            }
+        }
+        idx += 1;
+    }
+    nodes
+}
+
+/// A value starts in the current container, returns its name in the path. The node is
+/// recorded if it is an array item, the field node is recorded at its key.
+fn value_start(
+    stack: &mut [(Container, bool)],
+    path: &[String],
+    line: usize,
+    nodes: &mut Vec<(usize, String)>,
+) -> Option<String> {
+    match stack.last_mut() {
+        Some((Container::Array(len), _)) => {
+            let name = len.to_string();
+            *len += 1;
+            nodes.push((line, join_path(path, &name)));
+            Some(name)
+        }
+        Some((Container::Object(_, key), _)) => key.take(),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_number_text() {
+        let data = r#"{"a": 1.50, "b": 123456789012345678901234567890, "c": [-1, "2", 1E5]}"#;
+        let (value, annotations) = parse(data, false, false, &Progress::default()).unwrap();
+        assert_eq!(value["a"], json!(1.5));
+        assert_eq!(annotations["/a"].raw_number.as_deref(), Some("1.50"));
+        assert_eq!(
+            annotations["/b"].raw_number.as_deref(),
+            Some("123456789012345678901234567890")
+        );
+        assert!(!annotations.contains_key("/c/0"));
+        assert_eq!(annotations["/c/2"].raw_number.as_deref(), Some("1E5"));
+
+        let expected = r#"{
+  "a": 1.50,
+  "b": 123456789012345678901234567890,
+  "c": [
+    -1,
+    "2",
+    1E5
+  ]
+}"#;
+        assert_eq!(to_string(&value, "", &annotations).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_find_cut() {
+        let data = r#"{"a": [1, 2"#;
+        let cut = find_cut(data).unwrap();
+        assert_eq!(&data[..cut.offset], r#"{"a": [1"#);
+        assert_eq!(cut.stack, b"{[");
+        assert!(cut.has_items);
+        assert_eq!(cut.key, None);
+
+        let data = r#"{"a": 1, "b": tr"#;
+        let cut = find_cut(data).unwrap();
+        assert_eq!(&data[..cut.offset], r#"{"a": 1"#);
+        assert_eq!(cut.stack, b"{");
+        let (start, end) = cut.key.unwrap();
+        assert_eq!(&data[start..end], r#""b""#);
+
+        let data = r#"[{"a": "x\"y"#;
+        let cut = find_cut(data).unwrap();
+        assert_eq!(&data[..cut.offset], r#"[{"#);
+        assert!(!cut.has_items);
+
+        assert!(find_cut("").is_none());
+        assert!(find_cut("tru").is_none());
+    }
+
+    #[test]
+    fn test_recover() {
+        let progress = Progress::default();
+        let data = r#"{"a": 1, "b": [1, 2"#;
+        assert!(parse(data, false, false, &progress).is_err());
+
+        let (value, annotations) = parse(data, true, false, &progress).unwrap();
+        assert_eq!(value, json!({"a": 1, "b": [1, null]}));
+        assert!(annotations["/b/1"].error.is_some());
+
+        let (value, annotations) = parse(r#"{"a": 1, "b": tr"#, true, false, &progress).unwrap();
+        assert_eq!(value, json!({"a": 1, "b": null}));
+        assert!(annotations["/b"].error.is_some());
+
+        let (value, annotations) = parse(r#"{"a": 1, "b""#, true, false, &progress).unwrap();
+        assert_eq!(value, json!({"a": 1, ERROR_KEY: null}));
+        assert!(annotations[&format!("/{ERROR_KEY}")].error.is_some());
+    }
+
+    #[test]
+    fn test_recover_jsonc() {
+        let progress = Progress::default();
+        let data = "{\n  // the name\n  \"a\": 1,\n  \"b\": [1, 2";
+        let (value, annotations) = parse(data, true, true, &progress).unwrap();
+        assert_eq!(value, json!({"a": 1, "b": [1, null]}));
+        assert_eq!(annotations["/a"].comments, vec!["the name"]);
+        assert!(annotations["/b/1"].error.is_some());
+    }
+
+    #[test]
+    fn test_jsonc() {
+        let progress = Progress::default();
+        let data = "{\n  // the name\n  \"a\": \"// not a comment\", // trailing\n  /* é */\n  \"b\": 1\n}";
+        assert!(parse(data, false, false, &progress).is_err());
+
+        let (value, annotations) = parse(data, false, true, &progress).unwrap();
+        assert_eq!(value, json!({"a": "// not a comment", "b": 1}));
+        assert_eq!(annotations["/a"].comments, vec!["the name", "trailing"]);
+        assert_eq!(annotations["/b"].comments, vec!["é"]);
+    }
+
+    #[test]
+    fn test_recover_nothing() {
+        let progress = Progress::default();
+        assert!(parse("tru", true, false, &progress).is_err());
+        assert!(parse("", true, false, &progress).is_err());
+    }
 }
@@ -1,16 +1,499 @@
+use std::borrow::Cow;
+
 use anyhow::{Context, Result};
-use serde_json::Value;
+use serde_json::{Map, Number, Value};
+use toml_edit::{DocumentMut, Item, RawString, Table, TomlError};
+
+use crate::tree::pointer::{join_pointer, split_pointer};
+use crate::tree::{
+    non_finite_number, to_pointer, Annotations, ContentType, DatetimeKind, ParseError, Progress,
+};
 
 #[inline(always)]
-pub fn parse(data: &str) -> Result<Value> {
-    toml::from_str(data).context("parse toml")
+pub fn parse(data: &str, progress: &Progress) -> Result<(Value, Annotations)> {
+    let document = data.parse::<DocumentMut>().map_err(|err: TomlError| {
+        // The message may have multiple lines, like "invalid string\nexpected `\"`".
+        let message = err.message().trim().replace('\n', ", ");
+        let offset = err.span().map_or(0, |span| span.start);
+        ParseError::at_offset(ContentType::Toml, message, data, offset)
+    });
+    let document = document.context("parse toml")?;
+    progress.check()?;
+    let mut loader = Loader {
+        annotations: Annotations::new(),
+        path: vec![],
+        progress,
+    };
+    let value = loader.load_table(document.as_table())?;
+    Ok((value, loader.annotations))
 }
 
+/// Serialize the value at `path` to toml. The toml document must be a table, so an
+/// array is rendered under its own name, like `[[servers]]`. The values that toml cannot
+/// represent (such as null) are rendered as comments, so the output is always valid toml.
 #[inline(always)]
-pub fn to_string(value: &Value) -> Result<String> {
-    if let Value::Array(arr) = value {
-        // TODO: Handle array toml
-        return serde_json::to_string_pretty(arr).context("serialize json");
+pub fn to_string(value: &Value, path: &str, annotations: &Annotations) -> Result<String> {
+    let mut renderer = Renderer {
+        out: String::new(),
+        annotations,
+    };
+    match value {
+        Value::Object(obj) => {
+            let fields: Vec<_> = obj.iter().map(|(k, v)| (k.as_str(), v)).collect();
+            renderer.table(&[], path, &fields);
+        }
+        _ => {
+            let (parent, name) = split_pointer(path).unwrap_or(("", Cow::Borrowed("root")));
+            renderer.table(&[], parent, &[(&name, value)]);
+        }
+    }
+    Ok(renderer.out)
+}
+
+/// The toml loader, which builds the json value from the toml document. The original
+/// text of numbers and the comments are kept as annotations.
+struct Loader<'a> {
+    annotations: Annotations,
+    path: Vec<String>,
+    progress: &'a Progress,
+}
+
+impl Loader<'_> {
+    fn load_item(&mut self, item: &Item) -> Result<Value> {
+        Ok(match item {
+            Item::None => Value::Null,
+            Item::Value(value) => self.load_value(value)?,
+            Item::Table(table) => self.load_table(table)?,
+            Item::ArrayOfTables(tables) => {
+                let mut arr = Vec::with_capacity(tables.len());
+                for (idx, table) in tables.iter().enumerate() {
+                    self.path.push(idx.to_string());
+                    let mut comments = leading_comments(table.decor().prefix(), false);
+                    comments.extend(trailing_comment(table.decor().suffix()));
+                    self.add_comments(to_pointer(&self.path), comments);
+                    arr.push(self.load_table(table)?);
+                    self.path.pop();
+                }
+                Value::Array(arr)
+            }
+        })
+    }
+
+    fn load_table(&mut self, table: &Table) -> Result<Value> {
+        self.progress.check()?;
+        self.progress.add_nodes(1);
+        let mut obj = Map::with_capacity(table.len());
+        for (key, item) in table.iter() {
+            self.path.push(key.to_string());
+            let mut comments = match table.key(key) {
+                Some(key) => leading_comments(key.leaf_decor().prefix(), false),
+                None => vec![],
+            };
+            match item {
+                Item::Value(value) => comments.extend(trailing_comment(value.decor().suffix())),
+                Item::Table(table) => {
+                    comments.extend(leading_comments(table.decor().prefix(), false));
+                    comments.extend(trailing_comment(table.decor().suffix()));
+                }
+                _ => {}
+            }
+            self.add_comments(to_pointer(&self.path), comments);
+            obj.insert(key.to_string(), self.load_item(item)?);
+            self.path.pop();
+        }
+        Ok(Value::Object(obj))
+    }
+
+    fn load_value(&mut self, value: &toml_edit::Value) -> Result<Value> {
+        self.progress.check()?;
+        self.progress.add_nodes(1);
+        Ok(match value {
+            toml_edit::Value::String(s) => Value::String(s.value().clone()),
+            toml_edit::Value::Integer(i) => {
+                self.load_number(&i.display_repr(), Number::from(*i.value()))
+            }
+            toml_edit::Value::Float(f) => {
+                // Json cannot represent nan and inf, the toml text is kept instead.
+                let num =
+                    Number::from_f64(*f.value()).unwrap_or_else(|| non_finite_number(*f.value()));
+                self.load_number(&f.display_repr(), num)
+            }
+            toml_edit::Value::Boolean(b) => Value::Bool(*b.value()),
+            // The datetime has no counterpart in json value, it is stored as its text,
+            // and marked with its kind.
+            toml_edit::Value::Datetime(dt) => {
+                let dt = dt.value();
+                let kind = match (dt.date, dt.time, dt.offset) {
+                    (Some(_), Some(_), Some(_)) => DatetimeKind::OffsetDatetime,
+                    (Some(_), Some(_), None) => DatetimeKind::LocalDatetime,
+                    (Some(_), None, _) => DatetimeKind::LocalDate,
+                    (None, _, _) => DatetimeKind::LocalTime,
+                };
+                self.annotations
+                    .entry(to_pointer(&self.path))
+                    .or_default()
+                    .datetime = Some(kind);
+                Value::String(dt.to_string())
+            }
+            toml_edit::Value::Array(values) => {
+                // In a multi-line array, the first line of the prefix of an item follows
+                // the previous item (or the `[`), so the comment there belongs to it.
+                let mut arr = Vec::with_capacity(values.len());
+                let mut previous = to_pointer(&self.path);
+                for (idx, value) in values.iter().enumerate() {
+                    self.path.push(idx.to_string());
+                    let prefix = value.decor().prefix();
+                    self.add_comments(previous, trailing_comment(prefix).into_iter().collect());
+
+                    let path = to_pointer(&self.path);
+                    let mut comments = leading_comments(prefix, true);
+                    comments.extend(trailing_comment(value.decor().suffix()));
+                    self.add_comments(path.clone(), comments);
+                    previous = path;
+
+                    arr.push(self.load_value(value)?);
+                    self.path.pop();
+                }
+                let trailing = trailing_comment(Some(values.trailing()));
+                self.add_comments(previous, trailing.into_iter().collect());
+                Value::Array(arr)
+            }
+            toml_edit::Value::InlineTable(table) => {
+                let mut obj = Map::with_capacity(table.len());
+                for (key, value) in table.iter() {
+                    self.path.push(key.to_string());
+                    obj.insert(key.to_string(), self.load_value(value)?);
+                    self.path.pop();
+                }
+                Value::Object(obj)
+            }
+        })
+    }
+
+    /// Append the comments to the node at `path`.
+    fn add_comments(&mut self, path: String, comments: Vec<String>) {
+        if comments.is_empty() {
+            return;
+        }
+        let annotation = self.annotations.entry(path).or_default();
+        annotation.comments.extend(comments);
+    }
+
+    fn load_number(&mut self, text: &str, num: Number) -> Value {
+        // The json number cannot keep the original text like `0xff`, `1_000` or `1e3`,
+        // such text is kept in the annotation.
+        if num.to_string() == text {
+            return Value::Number(num);
+        }
+        self.annotations
+            .entry(to_pointer(&self.path))
+            .or_default()
+            .raw_number = Some(text.to_string());
+        Value::Number(num)
+    }
+}
+
+/// Returns the comment lines right above the node in the decor prefix. The last line of
+/// the prefix is the indentation before the node. If `inline` is true, the first line of
+/// the prefix follows the previous node and it is skipped, see [`trailing_comment`].
+fn leading_comments(prefix: Option<&RawString>, inline: bool) -> Vec<String> {
+    let prefix = prefix
+        .and_then(|prefix| prefix.as_str())
+        .unwrap_or_default();
+    let mut lines: Vec<_> = prefix.split('\n').collect();
+    lines.pop();
+    if inline && !lines.is_empty() {
+        lines.remove(0);
+    }
+
+    let mut comments = Vec::new();
+    for line in lines {
+        match line.trim().strip_prefix('#') {
+            Some(comment) => comments.push(comment.trim().to_string()),
+            // Only the comments right above the node are kept.
+            None => comments.clear(),
+        }
+    }
+    comments
+}
+
+/// Returns the comment on the first line of the decor.
+fn trailing_comment(decor: Option<&RawString>) -> Option<String> {
+    let decor = decor.and_then(|decor| decor.as_str())?;
+    let line = decor.split('\n').next()?;
+    let (_, comment) = line.split_once('#')?;
+    Some(comment.trim().to_string())
+}
+
+struct Renderer<'a> {
+    out: String,
+    annotations: &'a Annotations,
+}
+
+impl Renderer<'_> {
+    fn table(&mut self, keys: &[&str], path: &str, fields: &[(&str, &Value)]) {
+        // In toml, all the key/value pairs of a table must come before its sub-tables.
+        let mut tables = Vec::new();
+        for (key, value) in fields {
+            match value {
+                Value::Object(_) => tables.push((*key, *value)),
+                Value::Array(arr) if is_array_of_tables(arr) => tables.push((*key, *value)),
+                _ => self.key_value(key, &join_pointer(path, key), value),
+            }
+        }
+
+        for (key, value) in tables {
+            let mut child_keys = keys.to_vec();
+            child_keys.push(key);
+            let header = child_keys
+                .iter()
+                .map(|key| format_key(key))
+                .collect::<Vec<_>>()
+                .join(".");
+
+            let table_path = join_pointer(path, key);
+            let children = match value {
+                Value::Object(obj) => vec![(table_path, obj)],
+                Value::Array(arr) => arr
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(idx, item)| {
+                        let obj = item.as_object()?;
+                        Some((join_pointer(&table_path, &idx.to_string()), obj))
+                    })
+                    .collect(),
+                _ => unreachable!(),
+            };
+            let is_array = value.is_array();
+            for (table_path, obj) in children {
+                if !self.out.is_empty() {
+                    self.out.push('\n');
+                }
+                if is_array {
+                    self.out.push_str(&format!("[[{header}]]\n"));
+                } else {
+                    self.out.push_str(&format!("[{header}]\n"));
+                }
+                let fields: Vec<_> = obj.iter().map(|(k, v)| (k.as_str(), v)).collect();
+                self.table(&child_keys, &table_path, &fields);
+            }
+        }
+    }
+
+    fn key_value(&mut self, key: &str, path: &str, value: &Value) {
+        let key = format_key(key);
+        match self.to_inline(value, path, true) {
+            Some(text) => self.out.push_str(&format!("{key} = {text}\n")),
+            None => {
+                let json = serde_json::to_string(value).unwrap_or_default();
+                self.out
+                    .push_str(&format!("# {key} = {json}  # unsupported in toml\n"));
+            }
+        }
+    }
+
+    /// Render the value in inline form, returns `None` if the value cannot be represented in
+    /// toml.
+    fn to_inline(&self, value: &Value, path: &str, pretty: bool) -> Option<String> {
+        let annotation = self.annotations.get(path);
+        Some(match value {
+            Value::Null => return None,
+            Value::Bool(b) => b.to_string(),
+            Value::Number(num) => {
+                let raw = annotation.and_then(|annotation| annotation.raw_number.clone());
+                match raw {
+                    Some(raw) => raw,
+                    // The json number text is also valid in toml, except that the toml
+                    // integer is 64 bit signed.
+                    None if num.is_i64() || num.is_f64() => num.to_string(),
+                    None => return None,
+                }
+            }
+            // The datetime is written without quotes.
+            Value::String(s)
+                if annotation.is_some_and(|annotation| annotation.datetime.is_some()) =>
+            {
+                s.clone()
+            }
+            Value::String(s) => toml::Value::String(s.clone()).to_string(),
+            Value::Array(arr) => {
+                let items = arr
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, item)| {
+                        self.to_inline(item, &join_pointer(path, &idx.to_string()), false)
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                if pretty && items.len() > 1 {
+                    let mut text = String::from("[\n");
+                    for item in items {
+                        text.push_str(&format!("    {item},\n"));
+                    }
+                    text.push(']');
+                    text
+                } else {
+                    format!("[{}]", items.join(", "))
+                }
+            }
+            Value::Object(obj) => {
+                if obj.is_empty() {
+                    return Some(String::from("{}"));
+                }
+                let fields = obj
+                    .iter()
+                    .map(|(key, item)| {
+                        let item = self.to_inline(item, &join_pointer(path, key), false)?;
+                        Some(format!("{} = {item}", format_key(key)))
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                format!("{{ {} }}", fields.join(", "))
+            }
+        })
+    }
+}
+
+fn is_array_of_tables(arr: &[Value]) -> bool {
+    !arr.is_empty() && arr.iter().all(Value::is_object)
+}
+
+fn format_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        return key.to_string();
+    }
+    toml::Value::String(key.to_string()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_render_tables() {
+        let value = json!({
+            "title": "example",
+            "owner": {"name": "Tom", "dob": "1979-05-27T07:32:00-08:00"},
+            "servers": [{"ip": "10.0.0.1"}, {"ip": "10.0.0.2"}],
+            "ports": [8000, 8001],
+        });
+        let mut annotations = Annotations::new();
+        annotations
+            .entry(String::from("/owner/dob"))
+            .or_default()
+            .datetime = Some(DatetimeKind::OffsetDatetime);
+
+        let expected = r#"title = "example"
+ports = [
+    8000,
+    8001,
+]
+
+[owner]
+name = "Tom"
+dob = 1979-05-27T07:32:00-08:00
+
+[[servers]]
+ip = "10.0.0.1"
+
+[[servers]]
+ip = "10.0.0.2"
+"#;
+        assert_eq!(to_string(&value, "", &annotations).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_datetime() {
+        let data = r#"odt = 1979-05-27T07:32:00-08:00
+ldt = 1979-05-27T07:32:00
+ld = 1979-05-27
+lt = 07:32:00.999999
+"#;
+        let (value, annotations) = parse(data, &Progress::default()).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "odt": "1979-05-27T07:32:00-08:00",
+                "ldt": "1979-05-27T07:32:00",
+                "ld": "1979-05-27",
+                "lt": "07:32:00.999999",
+            })
+        );
+        let kinds = [
+            ("/odt", DatetimeKind::OffsetDatetime),
+            ("/ldt", DatetimeKind::LocalDatetime),
+            ("/ld", DatetimeKind::LocalDate),
+            ("/lt", DatetimeKind::LocalTime),
+        ];
+        for (path, kind) in kinds {
+            assert_eq!(annotations[path].datetime, Some(kind), "{path}");
+            let name = split_pointer(path).unwrap().1;
+            let expected = format!("{name} = {}\n", value[name.as_ref()].as_str().unwrap());
+            assert_eq!(
+                to_string(&value[name.as_ref()], path, &annotations).unwrap(),
+                expected
+            );
+        }
+        assert_eq!(to_string(&value, "", &annotations).unwrap(), data);
+    }
+
+    #[test]
+    fn test_render_unsupported() {
+        let value = json!({"a": null, "b": [1, null], "c": u64::MAX, "d": {"e": [{}]}});
+        let expected = r#"# a = null  # unsupported in toml
+# b = [1,null]  # unsupported in toml
+# c = 18446744073709551615  # unsupported in toml
+
+[d]
+
+[[d.e]]
+"#;
+        assert_eq!(
+            to_string(&value, "", &Annotations::new()).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_render_item() {
+        let value = json!(1);
+        assert_eq!(
+            to_string(&value, "/a/b", &Annotations::new()).unwrap(),
+            "b = 1\n"
+        );
+
+        let value = json!({"a b": {"c.d": 1}, "": 2});
+        let expected = r#""" = 2
+
+["a b"]
+"c.d" = 1
+"#;
+        assert_eq!(
+            to_string(&value, "", &Annotations::new()).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_render_parsed() {
+        let data = r#"int = 1_000
+hex = 0xff
+float = 1e3
+inf = -inf
+date = 2024-01-02
+
+[table]
+a = 1
+b = [
+    1,
+    2,
+]
+"#;
+        let (value, annotations) = parse(data, &Progress::default()).unwrap();
+        assert_eq!(to_string(&value, "", &annotations).unwrap(), data);
     }
-    toml::to_string_pretty(value).context("serialize toml")
 }
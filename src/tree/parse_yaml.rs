@@ -1,27 +1,1263 @@
-use anyhow::{bail, Context, Result};
-use serde::Deserialize;
-use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
 
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{Map, Number, Value};
+use serde_yml::libyml::emitter::{self, Emitter};
+use serde_yml::libyml::error::Mark;
+use serde_yml::libyml::parser::{Event, Parser, Scalar, ScalarStyle};
+use serde_yml::libyml::tag::Tag;
+
+use crate::tree::pointer::join_pointer;
+use crate::tree::{
+    attach_comments, non_finite_number, to_pointer, Annotation, Annotations, Comment, Comments,
+    ContentType, ObjectKeys, ParseError, Progress, ERROR_KEY,
+};
+
+const MERGE_KEY: &str = "<<";
+
+const CORE_TAG_PREFIX: &str = "tag:yaml.org,2002:";
+
+/// Parse the yaml data, in `lenient` mode, the error is replaced by an error node, see
+/// [`Loader::recover`].
 #[inline(always)]
-pub fn parse(data: &str) -> Result<Value> {
-    let mut values = Vec::with_capacity(1);
-    for document in serde_yml::Deserializer::from_str(data) {
-        let value = Value::deserialize(document).context("parse yaml")?;
-        values.push(value);
-    }
+pub fn parse(data: &str, lenient: bool, progress: &Progress) -> Result<(Value, Annotations)> {
+    let mut loader = Loader::new(data, lenient, progress);
+    let documents = loader.load_stream().context("parse yaml")?;
 
-    if values.is_empty() {
+    if documents.is_empty() {
         bail!("no document found in yaml data");
     }
 
-    if values.len() == 1 {
-        return Ok(values.into_iter().next().unwrap());
+    if documents.len() == 1 {
+        let (value, annotations) = documents.into_iter().next().unwrap();
+        if let Some(message) = annotations.get("").and_then(|a| a.error.as_ref()) {
+            // Nothing can be recovered.
+            let err = loader.error.unwrap_or_else(|| anyhow!("{message}"));
+            return Err(err).context("parse yaml");
+        }
+        return Ok((value, annotations));
     }
 
-    Ok(Value::Array(values))
+    // Multiple documents are stored as an array and marked as documents, so the paths of
+    // each document should be prefixed with the document index.
+    let mut values = Vec::with_capacity(documents.len());
+    let mut all_annotations = Annotations::new();
+    for (idx, (value, annotations)) in documents.into_iter().enumerate() {
+        let prefix = format!("/{idx}");
+        for (path, annotation) in annotations {
+            all_annotations.insert(format!("{prefix}{path}"), annotation.with_prefix(&prefix));
+        }
+        all_annotations
+            .entry(format!("/{idx}"))
+            .or_default()
+            .document = true;
+        values.push(value);
+    }
+
+    Ok((Value::Array(values), all_annotations))
 }
 
+/// Serialize the value at `path`, the tags, the original text of numbers and the
+/// duplicate keys in annotations are kept in the output.
 #[inline(always)]
-pub fn to_string(values: &Value) -> Result<String> {
-    serde_yml::to_string(values).context("serialize yaml")
+pub fn to_string(value: &Value, path: &str, annotations: &Annotations) -> Result<String> {
+    let mut buf = Vec::new();
+    let mut emitter = Emitter::new(Box::new(&mut buf));
+    emit(&mut emitter, emitter::Event::StreamStart)?;
+    emit(&mut emitter, emitter::Event::DocumentStart)?;
+    emit_value(&mut emitter, value, path, annotations)?;
+    emit(&mut emitter, emitter::Event::DocumentEnd)?;
+    emit(&mut emitter, emitter::Event::StreamEnd)?;
+    emitter
+        .flush()
+        .map_err(|err| anyhow!("{err:?}"))
+        .context("serialize yaml")?;
+    drop(emitter);
+    String::from_utf8(buf).context("serialize yaml")
+}
+
+fn emit(emitter: &mut Emitter, event: emitter::Event) -> Result<()> {
+    emitter
+        .emit(event)
+        .map_err(|err| anyhow!("{err:?}"))
+        .context("serialize yaml")
+}
+
+fn emit_scalar(
+    emitter: &mut Emitter,
+    tag: Option<String>,
+    value: &str,
+    style: emitter::ScalarStyle,
+) -> Result<()> {
+    let scalar = emitter::Scalar { tag, value, style };
+    emit(emitter, emitter::Event::Scalar(scalar))
+}
+
+/// Emit the events of the value, the annotations are emitted as they are instead of
+/// being stored in a yaml value, which cannot hold the duplicate keys or the number
+/// texts.
+fn emit_value(
+    emitter: &mut Emitter,
+    value: &Value,
+    path: &str,
+    annotations: &Annotations,
+) -> Result<()> {
+    let annotation = annotations.get(path);
+    let tag = annotation
+        .and_then(|annotation| annotation.tag.as_deref())
+        .map(tag_uri);
+
+    match value {
+        Value::Null => emit_scalar(emitter, tag, "null", emitter::ScalarStyle::Plain),
+        Value::Bool(b) => {
+            let text = if *b { "true" } else { "false" };
+            emit_scalar(emitter, tag, text, emitter::ScalarStyle::Plain)
+        }
+        Value::Number(num) => {
+            let text = annotation
+                .and_then(|annotation| annotation.raw_number.clone())
+                .unwrap_or_else(|| num.to_string());
+            emit_scalar(emitter, tag, &text, emitter::ScalarStyle::Plain)
+        }
+        Value::String(s) => emit_scalar(emitter, tag, s, string_style(s)),
+        Value::Array(arr) => {
+            emit(
+                emitter,
+                emitter::Event::SequenceStart(emitter::Sequence { tag }),
+            )?;
+            for (idx, item) in arr.iter().enumerate() {
+                let path = join_pointer(path, &idx.to_string());
+                emit_value(emitter, item, &path, annotations)?;
+            }
+            emit(emitter, emitter::Event::SequenceEnd)
+        }
+        Value::Object(obj) => {
+            emit(
+                emitter,
+                emitter::Event::MappingStart(emitter::Mapping { tag }),
+            )?;
+            for (key, item) in obj {
+                let path = join_pointer(path, key);
+                let annotation = annotations.get(&path);
+                // The typed key and the source key of the duplicate field are emitted
+                // instead of the key it is stored under.
+                match annotation.and_then(|annotation| annotation.key.as_ref()) {
+                    Some(typed) => emit_value(emitter, typed, "", &Annotations::new())?,
+                    None => {
+                        let key = annotation
+                            .and_then(|annotation| annotation.source_key.as_deref())
+                            .unwrap_or(key);
+                        emit_scalar(emitter, None, key, string_style(key))?;
+                    }
+                }
+                emit_value(emitter, item, &path, annotations)?;
+            }
+            emit(emitter, emitter::Event::MappingEnd)
+        }
+    }
+}
+
+/// Returns the full tag for the emitter, the core tags like `!!binary` are shortened
+/// back by the emitter, while the '!' in the shorthand would be escaped.
+fn tag_uri(tag: &str) -> String {
+    match tag.strip_prefix("!!") {
+        Some(name) => format!("{CORE_TAG_PREFIX}{name}"),
+        None => tag.to_string(),
+    }
+}
+
+/// Returns the style of the string scalar, the strings that would be resolved to other
+/// types in plain style are quoted.
+fn string_style(s: &str) -> emitter::ScalarStyle {
+    if s.contains('\n') {
+        return emitter::ScalarStyle::Literal;
+    }
+    let resolved = resolve_null(s)
+        .or_else(|| resolve_bool(s))
+        .or_else(|| resolve_int(s))
+        .or_else(|| resolve_float(s));
+    // The yaml 1.1 booleans are still resolved as booleans by many parsers.
+    let yaml11_bool = matches!(
+        s.to_lowercase().as_str(),
+        "y" | "yes" | "n" | "no" | "on" | "off"
+    );
+    if resolved.is_some() || yaml11_bool {
+        emitter::ScalarStyle::SingleQuoted
+    } else {
+        emitter::ScalarStyle::Any
+    }
+}
+
+/// Returns the key in yaml flow style, which is a single line, like `[a, 1]`.
+pub fn key_text(key: &Value) -> String {
+    match key {
+        Value::Null => String::from("null"),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(num) => num.to_string(),
+        Value::String(s) => {
+            // The flow indicators are allowed in block plain scalars, but not in flow.
+            let plain = match serde_yml::to_string(s) {
+                Ok(text) => text.trim_end() == s && !s.contains([',', '[', ']', '{', '}']),
+                Err(_) => false,
+            };
+            if plain {
+                s.clone()
+            } else {
+                // The json string is also a valid yaml double-quoted scalar.
+                key.to_string()
+            }
+        }
+        Value::Array(arr) => {
+            let items: Vec<_> = arr.iter().map(key_text).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(obj) => {
+            let fields: Vec<_> = obj
+                .iter()
+                .map(|(key, value)| {
+                    let key = key_text(&Value::String(key.clone()));
+                    format!("{key}: {}", key_text(value))
+                })
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+    }
+}
+
+/// The yaml loader, which builds the json value from the yaml events. Different from
+/// deserializing with serde, the loader keeps the anchors, aliases, merge keys, tags and
+/// comments as annotations, so that we can show where a value comes from.
+struct Loader<'a> {
+    data: &'a str,
+    parser: Parser<'a>,
+
+    /// The value and the path of each anchor, the later definition of the same name
+    /// replaces the earlier one. The path is `None` if the anchor is not a node in the
+    /// tree, like an anchor defined in a key.
+    anchors: HashMap<String, (Value, Option<String>)>,
+    annotations: Annotations,
+
+    /// The parser drops the comments, so they are scanned from the source, and attached
+    /// to the nodes by lines.
+    comments: Comments,
+    /// The byte offsets of the comment markers keyed by line, to drop the `#` lines in
+    /// the multi-line scalars, see [`Loader::remove_scalar_comments`].
+    comment_offsets: HashMap<usize, usize>,
+    nodes: Vec<(usize, String)>,
+
+    path: Vec<String>,
+
+    lenient: bool,
+    /// The error recovered in lenient mode, the parser cannot go on after it.
+    error: Option<anyhow::Error>,
+
+    progress: &'a Progress,
+}
+
+/// The fields merged from `<<` key, see: <https://yaml.org/type/merge.html>.
+struct Merge {
+    source: String,
+    /// The path of the anchor of the source, see [`Loader::anchors`].
+    source_path: Option<String>,
+    fields: Map<String, Value>,
+}
+
+enum MappingEntry {
+    Field(String, Value),
+    Merge(Vec<Merge>),
+}
+
+impl<'a> Loader<'a> {
+    fn new(data: &'a str, lenient: bool, progress: &'a Progress) -> Self {
+        let (comments, comment_offsets) = scan_comments(data);
+        Self {
+            data,
+            parser: Parser::new(Cow::Borrowed(data.as_bytes())),
+            anchors: HashMap::new(),
+            annotations: Annotations::new(),
+            comments,
+            comment_offsets,
+            nodes: vec![],
+            path: vec![],
+            lenient,
+            error: None,
+            progress,
+        }
+    }
+
+    fn next_event(&mut self) -> Result<(Event<'a>, Mark)> {
+        self.parser.parse_next_event().map_err(|err| {
+            // The position is reported by the parse error itself.
+            let message = err
+                .to_string()
+                .replacen(&format!(" at {}", err.mark()), "", 1);
+            error_at(&err.mark(), message)
+        })
+    }
+
+    fn load_stream(&mut self) -> Result<Vec<(Value, Annotations)>> {
+        let mut documents = Vec::with_capacity(1);
+        loop {
+            let value = match self.next_event() {
+                Ok((Event::StreamStart | Event::DocumentEnd, _)) => continue,
+                Ok((Event::StreamEnd, _)) => return Ok(documents),
+                Ok((Event::DocumentStart, _)) => {
+                    // The anchors cannot be referenced across documents.
+                    self.anchors.clear();
+                    match self.next_event() {
+                        Ok((event, mark)) => self.load_node(event, mark),
+                        Err(err) => Err(err),
+                    }
+                }
+                Ok((_, mark)) => Err(error_at(&mark, "unexpected yaml event")),
+                // The error between documents is shown as an error document.
+                Err(err) => Err(err),
+            };
+            let value = self.recover(value)?;
+
+            let nodes = std::mem::take(&mut self.nodes);
+            attach_comments(&self.comments, &nodes, &mut self.annotations);
+            documents.push((value, std::mem::take(&mut self.annotations)));
+            if self.error.is_some() {
+                return Ok(documents);
+            }
+        }
+    }
+
+    fn load_node(&mut self, event: Event<'a>, mark: Mark) -> Result<Value> {
+        self.progress.check()?;
+        self.progress.add_nodes(1);
+        match event {
+            Event::Alias(_) => {
+                let name = anchor_name(self.data, &mark);
+                // The alias refers to the latest definition of the anchor before it.
+                let (value, anchor_path) = match self.anchors.get(&name) {
+                    Some((value, path)) => (value.clone(), path.clone()),
+                    None => {
+                        let err = error_at(&mark, format!("unknown anchor '{name}'"));
+                        return self.skip(err);
+                    }
+                };
+                self.annotate(|annotation| {
+                    annotation.alias = Some(name);
+                    annotation.anchor_path = anchor_path;
+                });
+                Ok(value)
+            }
+            Event::Scalar(scalar) => {
+                self.remove_scalar_comments(&scalar, &mark);
+                let anchor = scalar
+                    .anchor
+                    .as_ref()
+                    .map(|_| anchor_name(self.data, &mark));
+                let value = match resolve_scalar(&scalar) {
+                    Ok(value) => value,
+                    Err(err) => return self.skip(error_at(&mark, err)),
+                };
+                if let Value::Number(num) = &value {
+                    // Such as `0x1F` and `+12`, the json number cannot keep them.
+                    let text = String::from_utf8_lossy(&scalar.value);
+                    if num.to_string() != text {
+                        let text = text.into_owned();
+                        self.annotate(|annotation| annotation.raw_number = Some(text));
+                    }
+                }
+                self.define_tag(scalar.tag.as_ref());
+                self.define_anchor(anchor, &value);
+                Ok(value)
+            }
+            Event::SequenceStart(start) => {
+                let anchor = start.anchor.as_ref().map(|_| anchor_name(self.data, &mark));
+                let value = self.load_sequence()?;
+                self.define_tag(start.tag.as_ref());
+                self.define_anchor(anchor, &value);
+                Ok(value)
+            }
+            Event::MappingStart(start) => {
+                let anchor = start.anchor.as_ref().map(|_| anchor_name(self.data, &mark));
+                let value = self.load_mapping()?;
+                self.define_tag(start.tag.as_ref());
+                self.define_anchor(anchor, &value);
+                Ok(value)
+            }
+            _ => Err(error_at(&mark, "unexpected yaml event")),
+        }
+    }
+
+    fn load_sequence(&mut self) -> Result<Value> {
+        let mut arr = Vec::new();
+        loop {
+            let (event, mark) = match self.next_event() {
+                Ok((Event::SequenceEnd, _)) => return Ok(Value::Array(arr)),
+                Ok(event) => event,
+                Err(err) => {
+                    self.path.push(arr.len().to_string());
+                    let value = self.recover(Err(err));
+                    self.path.pop();
+                    arr.push(value?);
+                    return Ok(Value::Array(arr));
+                }
+            };
+
+            self.path.push(arr.len().to_string());
+            self.add_node(&mark);
+            let value = self.load_node(event, mark);
+            let value = self.recover(value);
+            self.path.pop();
+            arr.push(value?);
+            if self.error.is_some() {
+                return Ok(Value::Array(arr));
+            }
+        }
+    }
+
+    fn load_mapping(&mut self) -> Result<Value> {
+        let mut entries = Vec::new();
+        let mut keys = ObjectKeys::default();
+        loop {
+            let key = match self.next_event() {
+                Ok((Event::MappingEnd, _)) => break,
+                Ok((event, mark)) => self.load_key(event, mark).map(|key| (key, mark)),
+                Err(err) => Err(err),
+            };
+            let ((key, typed), key_mark) = match key {
+                Ok(key) => key,
+                Err(err) => {
+                    // The key is unknown, the error node has its own key.
+                    let key = keys.insert(ERROR_KEY, None).stored;
+                    self.path.push(key.clone());
+                    let value = self.recover(Err(err));
+                    self.path.pop();
+                    entries.push(MappingEntry::Field(key, value?));
+                    break;
+                }
+            };
+
+            let next = match self.next_event() {
+                Ok((event, mark)) if typed.is_none() && key == MERGE_KEY => {
+                    match self.load_merge(event, mark) {
+                        Ok(merges) => {
+                            entries.push(MappingEntry::Merge(merges));
+                            continue;
+                        }
+                        // Shown as an error node under the merge key.
+                        Err(err) => Err(err),
+                    }
+                }
+                next => next,
+            };
+
+            let object_key = keys.insert(&key, typed);
+            object_key.annotate(&self.path, &key, &mut self.annotations);
+            let key = object_key.stored;
+
+            self.path.push(key.clone());
+            self.add_node(&key_mark);
+            let value = match next {
+                Ok((event, mark)) => self.load_node(event, mark),
+                Err(err) => Err(err),
+            };
+            let value = self.recover(value);
+            self.path.pop();
+            entries.push(MappingEntry::Field(key, value?));
+            if self.error.is_some() {
+                break;
+            }
+        }
+
+        let mut obj = Map::with_capacity(entries.len());
+        for entry in entries {
+            match entry {
+                MappingEntry::Field(key, value) => {
+                    obj.insert(key, value);
+                }
+                MappingEntry::Merge(merges) => {
+                    for merge in merges {
+                        for (key, value) in merge.fields {
+                            // The explicit fields always take precedence over the merged
+                            // fields, no matter where the merge key is.
+                            if keys.contains(&key) || obj.contains_key(&key) {
+                                continue;
+                            }
+
+                            self.path.push(key.clone());
+                            let source = merge.source.clone();
+                            let source_path = merge.source_path.clone();
+                            self.annotate(|annotation| {
+                                annotation.merged_from = Some(source);
+                                annotation.anchor_path = source_path;
+                            });
+                            self.path.pop();
+
+                            obj.insert(key, value);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Value::Object(obj))
+    }
+
+    /// Load the mapping key, returns the key text and the typed key if the key is not a
+    /// string.
+    fn load_key(&mut self, event: Event<'a>, mark: Mark) -> Result<(String, Option<Value>)> {
+        // The annotations of keys are meaningless, but the anchors defined in keys still
+        // need to be recorded. The errors in keys are recovered by the mapping.
+        let lenient = std::mem::replace(&mut self.lenient, false);
+        let path = std::mem::take(&mut self.path);
+        let annotations = std::mem::take(&mut self.annotations);
+        let nodes = std::mem::take(&mut self.nodes);
+        let key = self.load_node(event, mark);
+        self.path = path;
+        self.annotations = annotations;
+        self.nodes = nodes;
+        self.lenient = lenient;
+
+        Ok(match key? {
+            Value::String(s) => (s, None),
+            key => (key_text(&key), Some(key)),
+        })
+    }
+
+    fn load_merge(&mut self, event: Event<'a>, mark: Mark) -> Result<Vec<Merge>> {
+        match event {
+            Event::SequenceStart(_) => {
+                let mut merges = Vec::new();
+                loop {
+                    let (event, mark) = self.next_event()?;
+                    if let Event::SequenceEnd = event {
+                        return Ok(merges);
+                    }
+                    merges.push(self.load_merge_source(event, mark)?);
+                }
+            }
+            _ => Ok(vec![self.load_merge_source(event, mark)?]),
+        }
+    }
+
+    fn load_merge_source(&mut self, event: Event<'a>, mark: Mark) -> Result<Merge> {
+        let source = match event {
+            Event::Alias(_) => anchor_name(self.data, &mark),
+            _ => String::new(),
+        };
+        let source_path = self.anchors.get(&source).and_then(|(_, path)| path.clone());
+
+        // The merge source itself is not a node in the tree.
+        let lenient = std::mem::replace(&mut self.lenient, false);
+        let path = std::mem::take(&mut self.path);
+        let annotations = std::mem::take(&mut self.annotations);
+        let nodes = std::mem::take(&mut self.nodes);
+        let value = self.load_node(event, mark);
+        self.path = path;
+        self.annotations = annotations;
+        self.nodes = nodes;
+        self.lenient = lenient;
+
+        match value? {
+            Value::Object(fields) => Ok(Merge {
+                source,
+                source_path,
+                fields,
+            }),
+            _ => Err(error_at(&mark, "the merge key value should be a mapping")),
+        }
+    }
+
+    fn define_anchor(&mut self, anchor: Option<String>, value: &Value) {
+        if let Some(anchor) = anchor {
+            let path = (!self.path.is_empty()).then(|| to_pointer(&self.path));
+            self.anchors.insert(anchor.clone(), (value.clone(), path));
+            self.annotate(|annotation| annotation.anchor = Some(anchor));
+        }
+    }
+
+    fn define_tag(&mut self, tag: Option<&Tag>) {
+        if let Some(tag) = tag {
+            let tag = tag_name(tag);
+            self.annotate(|annotation| annotation.tag = Some(tag));
+        }
+    }
+
+    /// In lenient mode, the error is replaced by an error node at the current path. The
+    /// parser cannot go on after an error, so the loading stops, and the unterminated
+    /// containers are closed by their loaders.
+    fn recover(&mut self, value: Result<Value>) -> Result<Value> {
+        match value {
+            Err(err) if self.lenient => {
+                self.annotate_error(&err);
+                self.error = Some(err);
+                Ok(Value::Null)
+            }
+            value => value,
+        }
+    }
+
+    /// Replace the invalid node with an error node in lenient mode. Different from
+    /// [`Loader::recover`], the node is invalid but the parser can go on, like an unknown
+    /// alias.
+    fn skip(&mut self, err: anyhow::Error) -> Result<Value> {
+        if !self.lenient {
+            return Err(err);
+        }
+        self.annotate_error(&err);
+        Ok(Value::Null)
+    }
+
+    fn annotate_error(&mut self, err: &anyhow::Error) {
+        // The error of the root value tells that nothing is recovered.
+        let message = format!("{err:#}");
+        self.annotate(|annotation| annotation.error = Some(message));
+    }
+
+    /// The lines of a block scalar or a multi-line quoted scalar are its content, the `#`
+    /// in them are not comments. The comment on the first line is kept, like the one
+    /// after the `|` indicator, it is scanned with the quotes in mind. The trailing
+    /// comment after the scalar on its last line belongs to the node too.
+    fn remove_scalar_comments(&mut self, scalar: &Scalar, mark: &Mark) {
+        let repr = match scalar.repr {
+            Some(repr) => repr,
+            None => return,
+        };
+        let start = mark.index() as usize;
+        let span = start..start + repr.len();
+        let first = mark.line() as usize;
+        let lines = repr.iter().filter(|c| **c == b'\n').count();
+        for line in first + 1..=first + lines {
+            let inside = self
+                .comment_offsets
+                .get(&line)
+                .is_some_and(|offset| span.contains(offset));
+            if inside {
+                self.comments.remove(&line);
+            }
+        }
+
+        let last = first
+            + repr
+                .trim_ascii_end()
+                .iter()
+                .filter(|c| **c == b'\n')
+                .count();
+        if last > first {
+            self.nodes.push((last, to_pointer(&self.path)));
+        }
+    }
+
+    /// Record the current node starting at `mark`, to attach comments to it.
+    fn add_node(&mut self, mark: &Mark) {
+        self.nodes
+            .push((mark.line() as usize, to_pointer(&self.path)));
+    }
+
+    fn annotate<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut Annotation),
+    {
+        // The root value is not shown in the tree, but the document in a stream is, see
+        // [`parse`].
+        let annotation = self.annotations.entry(to_pointer(&self.path)).or_default();
+        f(annotation);
+    }
+}
+
+/// Scan the comments in the yaml source line by line. A comment starts with `#` that is
+/// at the beginning of the line or after a whitespace, and is not in a quoted scalar on
+/// the same line. The scalars spanning multiple lines are not known here, they are
+/// removed when loaded. Returns the comments and the byte offsets of their markers.
+fn scan_comments(data: &str) -> (Comments, HashMap<usize, usize>) {
+    let mut comments = Comments::new();
+    let mut offsets = HashMap::new();
+    let mut line_start = 0;
+    for (idx, line) in data.split('\n').enumerate() {
+        let offset = line_start;
+        line_start += line.len() + 1;
+        let mut quote = None;
+        let mut escaped = false;
+        let mut previous = None;
+        for (pos, c) in line.char_indices() {
+            let at_token_start = match previous {
+                None => true,
+                Some(previous) => char::is_whitespace(previous) || "[{,:".contains(previous),
+            };
+            previous = Some(c);
+            match quote {
+                Some('"') if escaped => escaped = false,
+                Some('"') if c == '\\' => escaped = true,
+                Some(q) if c == q => quote = None,
+                Some(_) => {}
+                None if (c == '\'' || c == '"') && at_token_start => quote = Some(c),
+                None if c == '#' && at_token_start => {
+                    offsets.insert(idx, offset + pos);
+                    comments.insert(
+                        idx,
+                        Comment {
+                            lines: vec![line[pos + 1..].trim().to_string()],
+                            start: idx,
+                            trailing: !line[..pos].trim().is_empty(),
+                        },
+                    );
+                    break;
+                }
+                None => {}
+            }
+        }
+    }
+    (comments, offsets)
+}
+
+/// Returns the anchor or alias name of the node at `mark`. The parser does not expose the
+/// name, so it is read from the source, the node starts at its properties, and the tag
+/// can be written before the anchor, like `!!str &name`.
+fn anchor_name(data: &str, mark: &Mark) -> String {
+    let mut rest = data.get(mark.index() as usize..).unwrap_or_default();
+    while rest.starts_with('!') {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        rest = rest[end..].trim_start();
+    }
+    let Some(rest) = rest.strip_prefix(['&', '*']) else {
+        return String::new();
+    };
+    // The parser only accepts the alphanumeric characters, `-` and `_` in names.
+    let end = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        .unwrap_or(rest.len());
+    rest[..end].to_string()
+}
+
+/// Returns the yaml syntax error at `mark`, to show the source around it.
+fn error_at(mark: &Mark, message: impl ToString) -> anyhow::Error {
+    let (line, column) = (mark.line() as usize, mark.column() as usize);
+    ParseError::new(ContentType::Yaml, message.to_string(), line, column).into()
+}
+
+/// Returns the tag in the form written in the source, the core tags are resolved to full
+/// uri by the parser, so we need to convert them back, like `!!binary`.
+fn tag_name(tag: &Tag) -> String {
+    let tag = String::from_utf8_lossy(tag);
+    match tag.strip_prefix(CORE_TAG_PREFIX) {
+        Some(name) => format!("!!{name}"),
+        None => tag.into_owned(),
+    }
+}
+
+fn resolve_scalar(scalar: &Scalar) -> Result<Value> {
+    let value = String::from_utf8_lossy(&scalar.value).into_owned();
+
+    if let Some(tag) = scalar.tag.as_ref() {
+        if *tag == format!("{CORE_TAG_PREFIX}str").as_str() {
+            return Ok(Value::String(value));
+        }
+        if *tag == Tag::NULL {
+            return match resolve_null(&value) {
+                Some(null) => Ok(null),
+                None => bail!("invalid null value '{value}'"),
+            };
+        }
+        if *tag == Tag::BOOL {
+            return match resolve_bool(&value) {
+                Some(b) => Ok(b),
+                None => bail!("invalid bool value '{value}'"),
+            };
+        }
+        if *tag == Tag::INT {
+            return match resolve_int(&value) {
+                Some(num) => Ok(num),
+                None => bail!("invalid int value '{value}'"),
+            };
+        }
+        if *tag == Tag::FLOAT {
+            return match resolve_int(&value).or_else(|| resolve_float(&value)) {
+                Some(num) => Ok(num),
+                None => bail!("invalid float value '{value}'"),
+            };
+        }
+    }
+
+    if scalar.style != ScalarStyle::Plain {
+        return Ok(Value::String(value));
+    }
+
+    Ok(resolve_null(&value)
+        .or_else(|| resolve_bool(&value))
+        .or_else(|| resolve_int(&value))
+        .or_else(|| resolve_float(&value))
+        .unwrap_or(Value::String(value)))
+}
+
+fn resolve_null(value: &str) -> Option<Value> {
+    match value {
+        "" | "~" | "null" | "Null" | "NULL" => Some(Value::Null),
+        _ => None,
+    }
+}
+
+fn resolve_bool(value: &str) -> Option<Value> {
+    match value {
+        "true" | "True" | "TRUE" => Some(Value::Bool(true)),
+        "false" | "False" | "FALSE" => Some(Value::Bool(false)),
+        _ => None,
+    }
+}
+
+fn resolve_int(value: &str) -> Option<Value> {
+    let (negative, unsigned) = match value.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+
+    let (radix, digits) = if let Some(digits) = unsigned.strip_prefix("0x") {
+        (16, digits)
+    } else if let Some(digits) = unsigned.strip_prefix("0o") {
+        (8, digits)
+    } else {
+        (10, unsigned)
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+
+    // The decimal integer too large for u64 is stored as a double. The sign `+` and the
+    // leading zeros are not allowed in json, the text is kept in the raw number.
+    if radix == 10 {
+        let trimmed = digits.trim_start_matches('0');
+        let trimmed = if trimmed.is_empty() { "0" } else { trimmed };
+        let sign = if negative { "-" } else { "" };
+        if let Ok(num) = format!("{sign}{trimmed}").parse::<Number>() {
+            return Some(Value::Number(num));
+        }
+    }
+
+    let num = u64::from_str_radix(digits, radix).ok()?;
+    if !negative {
+        return Some(Value::Number(num.into()));
+    }
+    if num == i64::MIN.unsigned_abs() {
+        return Some(Value::Number(i64::MIN.into()));
+    }
+    let num = i64::try_from(num).ok()?;
+    Some(Value::Number((-num).into()))
+}
+
+fn resolve_float(value: &str) -> Option<Value> {
+    let (negative, unsigned) = match value.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    // Json cannot represent nan and inf, the yaml text is kept in the raw number.
+    if matches!(unsigned, ".inf" | ".Inf" | ".INF") {
+        let float = if negative {
+            f64::NEG_INFINITY
+        } else {
+            f64::INFINITY
+        };
+        return Some(Value::Number(non_finite_number(float)));
+    }
+    if matches!(unsigned, ".nan" | ".NaN" | ".NAN") {
+        return Some(Value::Number(non_finite_number(f64::NAN)));
+    }
+
+    // The rust float parser accepts words like "inf" and "infinity", which are plain
+    // strings in yaml.
+    let starts_with_digit = unsigned.starts_with(|c: char| c.is_ascii_digit() || c == '.');
+    let valid_chars = unsigned
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '-' | '+'));
+    if !starts_with_digit || !valid_chars {
+        return None;
+    }
+
+    // Yaml also allows the sign `+` and the fraction without integer part, the text is
+    // kept in the raw number. The overflowed number like `1e999` is an infinity.
+    let sign = if negative { "-" } else { "" };
+    let zero = if unsigned.starts_with('.') { "0" } else { "" };
+    let num: f64 = format!("{sign}{zero}{unsigned}").parse().ok()?;
+    Some(Value::Number(
+        Number::from_f64(num).unwrap_or_else(|| non_finite_number(num)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn load(data: &str) -> (Value, Annotations) {
+        parse(data, false, &Progress::default()).unwrap()
+    }
+
+    #[test]
+    fn test_anchor() {
+        let data = r#"base: &base-1_A
+  a: 1
+list: !!seq &list [1, 2]
+alias: *base-1_A
+copy: *list
+flow: {x: &x "1", y: *x}
+"#;
+        let (value, annotations) = load(data);
+        assert_eq!(
+            value,
+            json!({
+                "base": {"a": 1},
+                "list": [1, 2],
+                "alias": {"a": 1},
+                "copy": [1, 2],
+                "flow": {"x": "1", "y": "1"},
+            })
+        );
+        assert_eq!(annotations["/base"].anchor.as_deref(), Some("base-1_A"));
+        assert_eq!(annotations["/list"].anchor.as_deref(), Some("list"));
+        assert_eq!(annotations["/list"].tag.as_deref(), Some("!!seq"));
+        assert_eq!(annotations["/alias"].alias.as_deref(), Some("base-1_A"));
+        assert_eq!(annotations["/alias"].anchor_path.as_deref(), Some("/base"));
+        assert_eq!(annotations["/copy"].anchor_path.as_deref(), Some("/list"));
+        assert_eq!(annotations["/flow/x"].anchor.as_deref(), Some("x"));
+        assert_eq!(
+            annotations["/flow/y"].anchor_path.as_deref(),
+            Some("/flow/x")
+        );
+    }
+
+    #[test]
+    fn test_anchor_redefined() {
+        let data = "a: &x 1\nb: *x\nc: &x 2\nd: *x\n";
+        let (value, annotations) = load(data);
+        assert_eq!(value, json!({"a": 1, "b": 1, "c": 2, "d": 2}));
+        assert_eq!(annotations["/b"].anchor_path.as_deref(), Some("/a"));
+        assert_eq!(annotations["/d"].anchor_path.as_deref(), Some("/c"));
+    }
+
+    #[test]
+    fn test_anchor_invalid_name() {
+        // Only the alphanumeric characters, `-` and `_` are accepted by the parser, the
+        // other names are syntax errors rather than mismatched aliases.
+        let progress = Progress::default();
+        for data in [
+            "a: &x\"y 1\nb: *x\"y\n",
+            "a: &x\\y 1\nb: *x\\y\n",
+            "a: &é 1\nb: *é\n",
+        ] {
+            assert!(parse(data, false, &progress).is_err(), "{data}");
+        }
+        let err = parse("a: 1\nb: *unknown\n", false, &progress).unwrap_err();
+        assert!(format!("{err:#}").contains("unknown anchor 'unknown'"));
+    }
+
+    #[test]
+    fn test_tags() {
+        let data = "a: !Ref x\nb: !!binary aGVsbG8=\nc: !!str 12\nd: !custom {k: 1}\ne: [!x 1]\n";
+        let (value, annotations) = load(data);
+        assert_eq!(
+            value,
+            json!({"a": "x", "b": "aGVsbG8=", "c": "12", "d": {"k": 1}, "e": [1]})
+        );
+        let tag = |path: &str| annotations[path].tag.as_deref();
+        assert_eq!(tag("/a"), Some("!Ref"));
+        assert_eq!(tag("/b"), Some("!!binary"));
+        assert_eq!(tag("/c"), Some("!!str"));
+        assert_eq!(tag("/d"), Some("!custom"));
+        assert_eq!(tag("/e/0"), Some("!x"));
+        assert!(!annotations.contains_key("/d/k"));
+
+        let expected = r#"a: !Ref x
+b: !!binary aGVsbG8=
+c: !!str '12'
+d: !custom
+  k: 1
+e:
+- !x 1
+"#;
+        assert_eq!(to_string(&value, "", &annotations).unwrap(), expected);
+        assert_eq!(
+            to_string(&value["d"], "/d", &annotations).unwrap(),
+            "!custom\nk: 1\n"
+        );
+    }
+
+    #[test]
+    fn test_to_string() {
+        let data = r#"a: 0x1F
+b: +12
+c: .5
+d: 1_000
+e: '123'
+f: 'yes'
+g: |-
+  multi
+  line
+h: '!%21'
+i: 1e3
+j: -.inf
+k: null
+l: 123456789012345678901234567890
+"#;
+        let (value, annotations) = load(data);
+        assert_eq!(value["a"], json!(31));
+        assert_eq!(value["d"], json!("1_000"));
+        assert_eq!(value["e"], json!("123"));
+        assert_eq!(value["f"], json!("yes"));
+        assert_eq!(annotations["/a"].raw_number.as_deref(), Some("0x1F"));
+        assert_eq!(annotations["/j"].raw_number.as_deref(), Some("-.inf"));
+        assert_eq!(
+            annotations["/l"].raw_number.as_deref(),
+            Some("123456789012345678901234567890")
+        );
+        assert_eq!(to_string(&value, "", &annotations).unwrap(), data);
+
+        // The value not parsed from yaml has no annotations.
+        let value = json!({"a": "1", "b": "no", "c": "x: y", "d": null, "e": [1.5, true]});
+        let expected = "a: '1'\nb: 'no'\nc: 'x: y'\nd: null\ne:\n- 1.5\n- true\n";
+        assert_eq!(
+            to_string(&value, "", &Annotations::new()).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let data = r#"base: &base
+  a: 1
+  b: 2
+other: &other
+  c: 3
+item:
+  <<: [*base, *other]
+  b: 4
+"#;
+        let (value, annotations) = load(data);
+        assert_eq!(value["item"], json!({"b": 4, "a": 1, "c": 3}));
+        assert_eq!(annotations["/item/a"].merged_from.as_deref(), Some("base"));
+        assert_eq!(annotations["/item/a"].anchor_path.as_deref(), Some("/base"));
+        assert_eq!(annotations["/item/c"].merged_from.as_deref(), Some("other"));
+        assert!(!annotations.contains_key("/item/b"));
+    }
+
+    #[test]
+    fn test_documents() {
+        let data = "a: &x 1\n---\nb: 2\nc: [3]\n...\n--- !doc\n- 4\n";
+        let (value, annotations) = load(data);
+        assert_eq!(value, json!([{"a": 1}, {"b": 2, "c": [3]}, [4]]));
+        for path in ["/0", "/1", "/2"] {
+            assert!(annotations[path].document, "{path}");
+        }
+        assert_eq!(annotations["/0/a"].anchor.as_deref(), Some("x"));
+        assert_eq!(annotations["/2"].tag.as_deref(), Some("!doc"));
+        assert_eq!(
+            to_string(&value[1], "/1", &annotations).unwrap(),
+            "b: 2\nc:\n- 3\n"
+        );
+
+        // A single document is not a stream, even with the explicit markers.
+        let (value, annotations) = load("---\na: 1\n...\n");
+        assert_eq!(value, json!({"a": 1}));
+        assert!(!annotations.contains_key(""));
+    }
+
+    #[test]
+    fn test_documents_anchor() {
+        // The anchors cannot be referenced across documents.
+        let data = "a: &x 1\n---\nb: *x\n";
+        let err = parse(data, false, &Progress::default()).unwrap_err();
+        assert!(format!("{err:#}").contains("unknown anchor 'x'"));
+
+        let (value, annotations) = parse(data, true, &Progress::default()).unwrap();
+        assert_eq!(value, json!([{"a": 1}, {"b": null}]));
+        assert!(annotations["/1/b"].error.is_some());
+    }
+
+    #[test]
+    fn test_documents_empty() {
+        assert!(parse("", false, &Progress::default()).is_err());
+        assert!(parse("# comment\n", false, &Progress::default()).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let data = "a: 1\na: 2\nb: x\na: 3\n'a': 4\n";
+        let (value, annotations) = load(data);
+        assert_eq!(
+            value,
+            json!({"a": 1, "a~2": 2, "b": "x", "a~3": 3, "a~4": 4})
+        );
+        assert!(annotations["/a"].duplicate);
+        assert_eq!(annotations["/a"].source_key, None);
+        for path in ["/a~02", "/a~03", "/a~04"] {
+            assert!(annotations[path].duplicate, "{path}");
+            assert_eq!(annotations[path].source_key.as_deref(), Some("a"), "{path}");
+        }
+        assert!(!annotations.contains_key("/b"));
+
+        let expected = "a: 1\na: 2\nb: x\na: 3\na: 4\n";
+        assert_eq!(to_string(&value, "", &annotations).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_duplicate_keys_stored() {
+        // The stored key of a duplicate does not take the key of another field.
+        let (value, annotations) = load("a~2: 1\na: 2\na: 3\n");
+        assert_eq!(value, json!({"a~2": 1, "a": 2, "a~3": 3}));
+        assert_eq!(annotations["/a~03"].source_key.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn test_typed_keys() {
+        let data = "? [k, 1]\n: v\n1: x\n'1': y\ntrue: t\nnull: n\n? {a: 1}\n: m\n1.5: f\n";
+        let (value, annotations) = load(data);
+        assert_eq!(
+            value,
+            json!({
+                "[k, 1]": "v",
+                "1": "x",
+                "1~2": "y",
+                "true": "t",
+                "null": "n",
+                "{a: 1}": "m",
+                "1.5": "f",
+            })
+        );
+        let key = |path: &str| annotations[path].key.clone();
+        assert_eq!(key("/[k, 1]"), Some(json!(["k", 1])));
+        assert_eq!(key("/1"), Some(json!(1)));
+        assert_eq!(key("/true"), Some(json!(true)));
+        assert_eq!(key("/null"), Some(json!(null)));
+        assert_eq!(key("/{a: 1}"), Some(json!({"a": 1})));
+        assert_eq!(key("/1.5"), Some(json!(1.5)));
+        // The string key `'1'` is different from the number key `1`.
+        assert_eq!(key("/1~02"), None);
+        assert!(!annotations["/1~02"].duplicate);
+        assert_eq!(annotations["/1~02"].source_key.as_deref(), Some("1"));
+
+        let expected = r#"? - k
+  - 1
+: v
+1: x
+'1': 'y'
+true: t
+null: 'n'
+? a: 1
+: m
+1.5: f
+"#;
+        assert_eq!(to_string(&value, "", &annotations).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_key_text() {
+        assert_eq!(key_text(&json!(["k", 1, null])), "[k, 1, null]");
+        assert_eq!(key_text(&json!({"a": [true]})), "{a: [true]}");
+        assert_eq!(key_text(&json!(["a, b", "x: y"])), r#"["a, b", "x: y"]"#);
+        assert_eq!(key_text(&json!("")), r#""""#);
+    }
+
+    fn comments(annotations: &Annotations) -> Vec<(&str, Vec<&str>)> {
+        let mut comments: Vec<_> = annotations
+            .iter()
+            .filter(|(_, annotation)| !annotation.comments.is_empty())
+            .map(|(path, annotation)| {
+                let lines = annotation.comments.iter().map(String::as_str).collect();
+                (path.as_str(), lines)
+            })
+            .collect();
+        comments.sort();
+        comments
+    }
+
+    #[test]
+    fn test_comments() {
+        let data = r#"# head
+a: 1 # trailing
+# above b
+
+# not attached
+b:
+  # above c
+  c: 2
+d: 'q # not a comment' # d
+"#;
+        let (_, annotations) = load(data);
+        assert_eq!(
+            comments(&annotations),
+            vec![
+                ("/a", vec!["head", "trailing"]),
+                ("/b", vec!["not attached"]),
+                ("/b/c", vec!["above c"]),
+                ("/d", vec!["d"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments_block_scalar() {
+        let data = r#"a: | # header
+  line 1
+  # not a comment
+  line 2
+# above b
+b: >
+  folded # text
+  # still text
+c: 3 # c
+"#;
+        let (value, annotations) = load(data);
+        assert_eq!(value["a"], json!("line 1\n# not a comment\nline 2\n"));
+        assert_eq!(value["b"], json!("folded # text # still text\n"));
+        assert_eq!(
+            comments(&annotations),
+            vec![
+                ("/a", vec!["header"]),
+                ("/b", vec!["above b"]),
+                ("/c", vec!["c"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments_multi_line_scalar() {
+        let data = r#"a: "multi
+  # in quotes
+  line" # a
+b: plain
+  text # b
+c: 1 # c
+"#;
+        let (value, annotations) = load(data);
+        assert_eq!(
+            value,
+            json!({"a": "multi # in quotes line", "b": "plain text", "c": 1})
+        );
+        assert_eq!(
+            comments(&annotations),
+            vec![("/a", vec!["a"]), ("/b", vec!["b"]), ("/c", vec!["c"])]
+        );
+    }
+
+    #[test]
+    fn test_comments_flow() {
+        let data = r#"a: [1, # one
+  2, # two
+  '3 # not a comment'] # three
+b: {x: 1, # x
+  # above y
+  y: 2}
+"#;
+        let (value, annotations) = load(data);
+        assert_eq!(
+            value,
+            json!({"a": [1, 2, "3 # not a comment"], "b": {"x": 1, "y": 2}})
+        );
+        // Only the first node on a line gets the comments.
+        assert_eq!(
+            comments(&annotations),
+            vec![
+                ("/a", vec!["one"]),
+                ("/a/1", vec!["two"]),
+                ("/a/2", vec!["three"]),
+                ("/b", vec!["x"]),
+                ("/b/y", vec!["above y"]),
+            ]
+        );
+    }
 }
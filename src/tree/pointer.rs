@@ -0,0 +1,120 @@
+use std::borrow::Cow;
+
+/// Returns the JSON Pointer (RFC 6901) of the item at `path`, like `/spec/containers/0`,
+/// which is used as the item id. The `~` and `/` in the names are escaped as `~0` and
+/// `~1`, so a key like `app.kubernetes.io/name` is not mixed up with a nested item. The
+/// pointer of the whole document is empty.
+pub fn to_pointer(path: &[String]) -> String {
+    let mut pointer = String::new();
+    for name in path {
+        pointer.push('/');
+        pointer.push_str(&escape(name));
+    }
+    pointer
+}
+
+/// Returns the names in the `pointer`, the reverse of [`to_pointer`].
+pub fn parse_pointer(pointer: &str) -> Vec<String> {
+    if pointer.is_empty() {
+        return Vec::new();
+    }
+    pointer
+        .strip_prefix('/')
+        .unwrap_or(pointer)
+        .split('/')
+        .map(|name| unescape(name).into_owned())
+        .collect()
+}
+
+/// Returns the pointer of the child `name` of the item at `parent`.
+pub(super) fn join_pointer(parent: &str, name: &str) -> String {
+    format!("{parent}/{}", escape(name))
+}
+
+/// Returns the pointer relative to the item at `prefix`, if `pointer` is a descendant
+/// of it.
+pub(super) fn strip_pointer_prefix<'p>(pointer: &'p str, prefix: &str) -> Option<&'p str> {
+    let rest = pointer.strip_prefix(prefix)?;
+    rest.starts_with('/').then_some(rest)
+}
+
+/// Returns the parent pointer and the name of the last item in the `pointer`.
+pub(super) fn split_pointer(pointer: &str) -> Option<(&str, Cow<'_, str>)> {
+    let (parent, name) = pointer.rsplit_once('/')?;
+    Some((parent, unescape(name)))
+}
+
+fn escape(name: &str) -> Cow<'_, str> {
+    if name.contains(['~', '/']) {
+        Cow::Owned(name.replace('~', "~0").replace('/', "~1"))
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+pub(super) fn unescape(name: &str) -> Cow<'_, str> {
+    if name.contains('~') {
+        // `~01` is `~1` rather than `/`, so `~1` is replaced first.
+        Cow::Owned(name.replace("~1", "/").replace("~0", "~"))
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_pointer() {
+        let names = path(&["spec", "app.kubernetes.io/name", "a~b", "~1", ""]);
+        let pointer = to_pointer(&names);
+        assert_eq!(pointer, "/spec/app.kubernetes.io~1name/a~0b/~01/");
+        assert_eq!(parse_pointer(&pointer), names);
+
+        assert_eq!(to_pointer(&[]), "");
+        assert!(parse_pointer("").is_empty());
+        assert_eq!(parse_pointer("/"), path(&[""]));
+    }
+
+    #[test]
+    fn test_join_pointer() {
+        assert_eq!(join_pointer("", "a"), "/a");
+        assert_eq!(join_pointer("/a", "b/c"), "/a/b~1c");
+        assert_eq!(join_pointer("/a", "~"), "/a/~0");
+    }
+
+    #[test]
+    fn test_strip_pointer_prefix() {
+        assert_eq!(strip_pointer_prefix("/a/b", "/a"), Some("/b"));
+        assert_eq!(strip_pointer_prefix("/a/b", ""), Some("/a/b"));
+        assert_eq!(strip_pointer_prefix("/ab", "/a"), None);
+        assert_eq!(strip_pointer_prefix("/a", "/a"), None);
+        assert_eq!(strip_pointer_prefix("/b", "/a"), None);
+    }
+
+    #[test]
+    fn test_split_pointer() {
+        let (parent, name) = split_pointer("/a/b~1c").unwrap();
+        assert_eq!(parent, "/a");
+        assert_eq!(name, "b/c");
+
+        let (parent, name) = split_pointer("/a").unwrap();
+        assert_eq!(parent, "");
+        assert_eq!(name, "a");
+
+        assert!(split_pointer("").is_none());
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("a"), "a");
+        assert_eq!(unescape("a~1b"), "a/b");
+        assert_eq!(unescape("a~0b"), "a~b");
+        assert_eq!(unescape("~01"), "~1");
+    }
+}
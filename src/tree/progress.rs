@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use anyhow::{bail, Result};
+
+/// The progress of loading the data, shared between the loading thread and the TUI.
+/// The loading can be cancelled, the loader stops at the next [`Progress::check`].
+#[derive(Debug, Default)]
+pub struct Progress {
+    /// The size of the data, zero if it is unknown, like reading from stdin.
+    total: AtomicUsize,
+    bytes: AtomicUsize,
+    nodes: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    pub fn set_total(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    pub fn add_bytes(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Set the bytes processed to `bytes` if it is larger, used when scanning the data
+    /// by position.
+    pub fn set_bytes(&self, bytes: usize) {
+        self.bytes.fetch_max(bytes, Ordering::Relaxed);
+    }
+
+    pub fn add_nodes(&self, nodes: usize) {
+        self.nodes.fetch_add(nodes, Ordering::Relaxed);
+    }
+
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.nodes.load(Ordering::Relaxed)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Returns an error if the loading is cancelled.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            bail!("loading is cancelled");
+        }
+        Ok(())
+    }
+}
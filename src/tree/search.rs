@@ -0,0 +1,437 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::Config;
+
+use super::pointer::join_pointer;
+use super::{parse_yaml, Annotations, Tree, ROOT_ID};
+
+/// What the search query is matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchTarget {
+    /// Both the field names and the scalar values.
+    All,
+    /// Only the field names.
+    Keys,
+    /// Only the scalar values.
+    Values,
+}
+
+/// The compiled search query, see [`Tree::search`].
+pub struct SearchQuery {
+    matcher: Matcher,
+    target: SearchTarget,
+}
+
+enum Matcher {
+    Regex(Regex),
+    /// The text is lowercase if the search is case-insensitive.
+    Text {
+        text: String,
+        case_sensitive: bool,
+    },
+}
+
+/// The items matched by [`Tree::search`].
+#[derive(Debug, Default)]
+pub struct SearchMatches {
+    /// The paths of the matched items, in the order they are shown.
+    pub paths: Vec<Vec<String>>,
+    /// The index of the first match at or after the `from` item.
+    pub start: usize,
+}
+
+/// An item listed by the path finder, see [`Tree::paths`].
+#[derive(Debug, Clone)]
+pub struct ItemPath {
+    /// The names of the item and its ancestors, the same as in the item id.
+    pub path: Vec<String>,
+    /// The path shown to the user, like `/metadata/app.kubernetes.io/name`. The names are
+    /// not escaped, and the fields with duplicate or typed keys are shown with the keys
+    /// in the source.
+    pub text: String,
+}
+
+/// The parts of an item matched by the search.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct SearchHit {
+    pub(super) key: bool,
+    pub(super) value: bool,
+}
+
+impl SearchTarget {
+    /// The next target, to switch the target in the search input.
+    pub fn next(self) -> Self {
+        match self {
+            Self::All => Self::Keys,
+            Self::Keys => Self::Values,
+            Self::Values => Self::All,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Keys => "keys",
+            Self::Values => "values",
+        }
+    }
+}
+
+impl SearchQuery {
+    pub fn new(
+        text: &str,
+        regex: bool,
+        case_sensitive: bool,
+        target: SearchTarget,
+    ) -> Result<Self> {
+        let matcher = if regex {
+            let regex = RegexBuilder::new(text)
+                .case_insensitive(!case_sensitive)
+                .build()
+                .map_err(|err| {
+                    // The syntax error shows the position in multiple lines, only the
+                    // reason is kept to be shown in the input line.
+                    let err = err.to_string();
+                    let reason = err.lines().last().unwrap_or_default();
+                    anyhow!("invalid regex: {}", reason.trim_start_matches("error: "))
+                })?;
+            Matcher::Regex(regex)
+        } else if case_sensitive {
+            Matcher::Text {
+                text: text.to_string(),
+                case_sensitive,
+            }
+        } else {
+            Matcher::Text {
+                text: text.to_lowercase(),
+                case_sensitive,
+            }
+        };
+        Ok(Self { matcher, target })
+    }
+
+    fn is_match(&self, s: &str) -> bool {
+        match &self.matcher {
+            Matcher::Regex(regex) => regex.is_match(s),
+            Matcher::Text {
+                text,
+                case_sensitive: true,
+            } => s.contains(text.as_str()),
+            Matcher::Text {
+                text,
+                case_sensitive: false,
+            } => s.to_lowercase().contains(text.as_str()),
+        }
+    }
+}
+
+impl Tree {
+    /// Search the names and the scalar values of all the items, including the items not
+    /// loaded yet, the matched items are highlighted. The search starts from the item at
+    /// `from`, usually the selected item, and wraps around. Pass `None` to clear the
+    /// search. When the tree is filtered, only the items shown are searched.
+    ///
+    /// In huge-file mode, only the containers opened before are searched.
+    pub fn search(
+        &mut self,
+        cfg: &Config,
+        query: Option<&SearchQuery>,
+        from: &[String],
+    ) -> SearchMatches {
+        let (hits, matches) = match query {
+            Some(query) => self.find(query, from, self.filter.as_ref()),
+            None => Default::default(),
+        };
+        self.search_hits = hits;
+        self.restyle(cfg);
+        matches
+    }
+
+    /// Only show the items matching the query and their ancestors, the matched items keep
+    /// all their children. Returns the paths of the matched items in the order they are
+    /// shown. Pass `None` to show all the items again.
+    pub fn filter(&mut self, cfg: &Config, query: Option<&SearchQuery>) -> Vec<Vec<String>> {
+        self.filter = None;
+        let mut paths = Vec::new();
+        if let Some(query) = query {
+            let (hits, matches) = self.find(query, &[], None);
+            let matched: HashSet<String> = hits.keys().cloned().collect();
+            let mut shown = HashSet::with_capacity(matched.len());
+            for id in matched.iter() {
+                for (idx, _) in id.match_indices('/').skip(1) {
+                    shown.insert(id[..idx].to_string());
+                }
+                shown.insert(id.clone());
+            }
+            self.filter = Some(Filter {
+                matched,
+                shown,
+                hits,
+            });
+            paths = matches.paths;
+        }
+        self.restyle(cfg);
+        paths
+    }
+
+    /// Returns the paths of all the items shown, including the items not loaded yet, in
+    /// the order they are shown. Used by the path finder.
+    ///
+    /// In huge-file mode, only the containers opened before are listed.
+    pub fn paths(&self) -> Vec<ItemPath> {
+        let mut paths = Vec::new();
+        match &self.root {
+            value @ (Value::Array(_) | Value::Object(_)) => {
+                self.collect_paths(&mut Vec::new(), "", "", value, &mut paths)
+            }
+            _ => {
+                let mut path = vec![String::from("root")];
+                paths.push(ItemPath {
+                    path: path.clone(),
+                    text: String::from("root"),
+                });
+                // The scalar root may be expanded as embedded data.
+                if let Some(value) = self.embedded.get(ROOT_ID) {
+                    self.collect_paths(&mut path, ROOT_ID, "root", value, &mut paths);
+                }
+            }
+        }
+        paths
+    }
+
+    fn collect_paths(
+        &self,
+        path: &mut Vec<String>,
+        id: &str,
+        text: &str,
+        value: &Value,
+        paths: &mut Vec<ItemPath>,
+    ) {
+        let children: Vec<(String, &Value)> = match value {
+            Value::Array(arr) => arr
+                .iter()
+                .enumerate()
+                .map(|(idx, item)| (idx.to_string(), item))
+                .collect(),
+            Value::Object(obj) => obj
+                .iter()
+                .map(|(field, item)| (field.clone(), item))
+                .collect(),
+            _ => return,
+        };
+        for (name, item) in children {
+            let child_id = join_pointer(id, &name);
+            if self
+                .filter
+                .as_ref()
+                .is_some_and(|filter| !filter.contains(&child_id))
+            {
+                continue;
+            }
+            // The embedded data may be expanded.
+            let item = self.embedded.get(&child_id).unwrap_or(item);
+            let annotation = self.annotations.get(&child_id);
+            let key = match annotation.and_then(|annotation| annotation.key.as_ref()) {
+                Some(key) => parse_yaml::key_text(key),
+                None => annotation
+                    .and_then(|annotation| annotation.source_key.clone())
+                    .unwrap_or_else(|| name.clone()),
+            };
+            let child_text = format!("{text}/{key}");
+            path.push(name);
+            paths.push(ItemPath {
+                path: path.clone(),
+                text: child_text.clone(),
+            });
+            self.collect_paths(path, &child_id, &child_text, item, paths);
+            path.pop();
+        }
+    }
+
+    fn find(
+        &self,
+        query: &SearchQuery,
+        from: &[String],
+        filter: Option<&Filter>,
+    ) -> (HashMap<String, SearchHit>, SearchMatches) {
+        let mut hits = HashMap::new();
+        let mut matches = SearchMatches::default();
+        let mut searcher = Searcher {
+            query,
+            annotations: &self.annotations,
+            embedded: &self.embedded,
+            filter,
+            from,
+            hits: &mut hits,
+            matches: &mut matches,
+        };
+        match &self.root {
+            value @ (Value::Array(_) | Value::Object(_)) => {
+                searcher.search_children(&mut Vec::new(), "", value)
+            }
+            value => searcher.search_item(&mut vec![String::from("root")], ROOT_ID, value, None),
+        }
+        (hits, matches)
+    }
+}
+
+/// The items shown in the filtered tree, see [`Tree::filter`].
+pub(super) struct Filter {
+    /// The matched items, their children are all shown.
+    matched: HashSet<String>,
+    /// The matched items and their ancestors.
+    shown: HashSet<String>,
+    pub(super) hits: HashMap<String, SearchHit>,
+}
+
+impl Filter {
+    /// Returns whether the item `id` is shown.
+    pub(super) fn contains(&self, id: &str) -> bool {
+        if self.shown.contains(id) {
+            return true;
+        }
+        id.match_indices('/')
+            .skip(1)
+            .any(|(idx, _)| self.matched.contains(&id[..idx]))
+    }
+}
+
+struct Searcher<'s> {
+    query: &'s SearchQuery,
+    annotations: &'s Annotations,
+    embedded: &'s HashMap<String, Value>,
+    filter: Option<&'s Filter>,
+    from: &'s [String],
+    hits: &'s mut HashMap<String, SearchHit>,
+    matches: &'s mut SearchMatches,
+}
+
+impl Searcher<'_> {
+    fn search_children(&mut self, path: &mut Vec<String>, id: &str, value: &Value) {
+        match value {
+            Value::Array(arr) => {
+                for (idx, item) in arr.iter().enumerate() {
+                    path.push(idx.to_string());
+                    self.search_item(path, &join_pointer(id, &idx.to_string()), item, None);
+                    path.pop();
+                }
+            }
+            Value::Object(obj) => {
+                for (field, item) in obj.iter() {
+                    path.push(field.clone());
+                    self.search_item(path, &join_pointer(id, field), item, Some(field));
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn search_item(
+        &mut self,
+        path: &mut Vec<String>,
+        id: &str,
+        value: &Value,
+        field: Option<&str>,
+    ) {
+        // The children of the hidden item are hidden too.
+        if self.filter.is_some_and(|filter| !filter.contains(id)) {
+            return;
+        }
+        if path == self.from {
+            self.matches.start = self.matches.paths.len();
+        }
+        let annotation = self.annotations.get(id);
+        // The embedded data may be expanded.
+        let value = self.embedded.get(id).unwrap_or(value);
+
+        let mut hit = SearchHit::default();
+        if self.query.target != SearchTarget::Values {
+            // The duplicate fields are shown with the key in the source.
+            let key = annotation
+                .and_then(|annotation| annotation.source_key.as_deref())
+                .or(field);
+            hit.key = key.is_some_and(|key| self.query.is_match(key));
+        }
+        if self.query.target != SearchTarget::Keys {
+            let raw_number = annotation.and_then(|annotation| annotation.raw_number.as_deref());
+            hit.value = match value {
+                Value::String(s) => self.query.is_match(s),
+                Value::Number(num) => match raw_number {
+                    Some(text) => self.query.is_match(text),
+                    None => self.query.is_match(&num.to_string()),
+                },
+                Value::Bool(b) => self.query.is_match(&b.to_string()),
+                Value::Null => self.query.is_match("null"),
+                Value::Array(_) | Value::Object(_) => false,
+            };
+        }
+        if hit.key || hit.value {
+            self.hits.insert(id.to_string(), hit);
+            self.matches.paths.push(path.clone());
+        }
+
+        self.search_children(path, id, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encoding::Encoding;
+    use crate::tree::{to_pointer, ContentType};
+
+    use super::*;
+
+    fn paths(data: &str, content_type: ContentType) -> Vec<(String, String)> {
+        let mut cfg = Config::default();
+        cfg.parse().unwrap();
+        let tree =
+            Tree::from_bytes(&cfg, data.as_bytes().to_vec(), content_type, Encoding::Auto).unwrap();
+        tree.paths()
+            .into_iter()
+            .map(|item| (to_pointer(&item.path), item.text))
+            .collect()
+    }
+
+    #[test]
+    fn test_paths() {
+        let data = r#"{"a/b": {"c~d": [1]}, "name": 1, "name": 2}"#;
+        let expected = [
+            ("/a~1b", "/a/b"),
+            ("/a~1b/c~0d", "/a/b/c~d"),
+            ("/a~1b/c~0d/0", "/a/b/c~d/0"),
+            ("/name", "/name"),
+            ("/name~02", "/name"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(id, text)| (id.to_string(), text.to_string()))
+            .collect();
+        assert_eq!(paths(data, ContentType::Json), expected);
+    }
+
+    #[test]
+    fn test_paths_typed_keys() {
+        let data = "? [k, 1]\n: v\n1: x\n";
+        let texts: Vec<_> = paths(data, ContentType::Yaml)
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect();
+        assert_eq!(texts, vec!["/[k, 1]", "/1"]);
+    }
+
+    #[test]
+    fn test_paths_root_scalar() {
+        assert_eq!(
+            paths("1", ContentType::Json),
+            vec![(String::from("/root"), String::from("root"))]
+        );
+    }
+}
@@ -1,7 +1,10 @@
+use std::fs;
 use std::io::Stdout;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
-use anyhow::Result;
-use crossterm::event::{Event, KeyEvent, MouseButton, MouseEventKind};
+use anyhow::{Context, Result};
+use crossterm::event::{self, Event, KeyEvent, MouseButton, MouseEventKind};
 use ratatui::backend::CrosstermBackend;
 use ratatui::layout::{Constraint, Layout, Position, Rect};
 use ratatui::{Frame, Terminal};
@@ -11,14 +14,19 @@ use crate::config::{Config, LayoutDirection};
 use crate::tree::Tree;
 use crate::ui::data_block::DataBlock;
 use crate::ui::header::{Header, HeaderContext};
+use crate::ui::path_finder::{FinderInput, PathFinder};
+use crate::ui::search_bar::{SearchBar, SearchInput, SearchMode};
 use crate::ui::tree_overview::TreeOverview;
 
-enum Refresh {
+/// The result of handling an event, see [`App::handle_event`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refresh {
     /// Update the TUI
     Update,
     /// Skip the update of the TUI
     Skip,
-    /// Quit the TUI and return to the shell
+    /// The quit key is pressed, quit the TUI and return to the shell. An app embedding
+    /// the viewer may close it instead.
     Quit,
 }
 
@@ -34,86 +42,197 @@ pub enum ScrollDirection {
     Down,
 }
 
-pub struct App<'a> {
-    cfg: &'a Config,
+/// The tree overview and the data block of the selected item, with an optional header.
+/// It can be run by [`App::show`], or embedded in another app by [`App::draw`] and
+/// [`App::handle_event`].
+pub struct App {
+    cfg: Config,
+    config_source: Option<ConfigSource>,
+    /// The error of reloading the config, shown in the header.
+    message: Option<String>,
+    /// The error of the last action, like expanding broken embedded data. It is shown in
+    /// the header until the next key is pressed.
+    error: Option<String>,
 
     focus: ElementInFocus,
     last_focus: Option<ElementInFocus>,
 
-    tree_overview: TreeOverview<'a>,
+    tree_overview: TreeOverview,
     tree_overview_area: Rect,
 
-    data_block: DataBlock<'a>,
+    data_block: DataBlock,
     data_block_area: Rect,
 
     layout_direction: LayoutDirection,
     layout_tree_size: u16,
 
-    header: Option<Header<'a>>,
+    header: Header,
     header_area: Rect,
     skip_header: bool,
+
+    search_bar: SearchBar,
+    search_bar_area: Rect,
+
+    /// The path finder popup, the keys are sent to it while it is opened.
+    path_finder: Option<PathFinder>,
+}
+
+/// Where the config is reloaded from, see [`App::set_config_source`].
+struct ConfigSource {
+    /// The config file, the config is reloaded when it is modified.
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    load: Box<dyn Fn() -> Result<Config>>,
 }
 
-impl<'a> App<'a> {
+impl App {
     const HEADER_HEIGHT: u16 = 1;
 
-    pub fn new(cfg: &'a Config, tree: Tree<'a>) -> Self {
+    /// The interval to check whether the config file is modified in [`App::show`].
+    const CONFIG_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Create the app with the parsed config, see [`Config::parse`].
+    pub fn new(cfg: Config, tree: Tree) -> Self {
         Self {
-            cfg,
             focus: ElementInFocus::TreeOverview,
             last_focus: None,
-            tree_overview: TreeOverview::new(cfg, tree),
+            tree_overview: TreeOverview::new(tree),
             tree_overview_area: Rect::default(),
-            data_block: DataBlock::new(cfg),
+            data_block: DataBlock::new(),
             data_block_area: Rect::default(),
             layout_direction: cfg.layout.direction,
             layout_tree_size: cfg.layout.tree_size,
-            header: None,
+            header: Header::default(),
             header_area: Rect::default(),
             skip_header: false,
+            search_bar: SearchBar::new(&cfg),
+            search_bar_area: Rect::default(),
+            path_finder: None,
+            cfg,
+            config_source: None,
+            message: None,
+            error: None,
         }
     }
 
+    /// Run the event loop in the whole terminal until the quit key is pressed.
     pub fn show(&mut self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> Result<()> {
-        terminal.draw(|frame| self.draw(frame))?;
+        terminal.draw(|frame| self.draw(frame, frame.size()))?;
 
         loop {
-            let refresh = match crossterm::event::read()? {
-                Event::Key(key) => self.on_key(key),
-                Event::Mouse(mouse) => match mouse.kind {
-                    MouseEventKind::Down(MouseButton::Left) => {
-                        self.on_click(mouse.column, mouse.row)
-                    }
-                    MouseEventKind::ScrollUp => {
-                        self.on_scroll(ScrollDirection::Up, mouse.column, mouse.row)
-                    }
-                    MouseEventKind::ScrollDown => {
-                        self.on_scroll(ScrollDirection::Down, mouse.column, mouse.row)
-                    }
-                    _ => Refresh::Skip,
-                },
-                // When resize happens, we need to redraw the widgets to fit the new size
-                Event::Resize(_, _) => Refresh::Update,
-                Event::FocusGained => self.on_focus_changed(true),
-                Event::FocusLost => self.on_focus_changed(false),
-                _ => Refresh::Skip,
+            let refresh = if event::poll(Self::CONFIG_CHECK_INTERVAL)? {
+                let event = event::read()?;
+                self.handle_event(&event)
+            } else {
+                self.check_config()
             };
-
             match refresh {
                 Refresh::Update => {}
                 Refresh::Skip => continue,
                 Refresh::Quit => return Ok(()),
             }
-            terminal.draw(|frame| self.draw(frame))?;
+            terminal.draw(|frame| self.draw(frame, frame.size()))?;
         }
     }
 
+    /// Handle the terminal event, returns whether the app should be drawn again. The
+    /// mouse events are located by the areas of the last [`App::draw`].
+    pub fn handle_event(&mut self, event: &Event) -> Refresh {
+        match event {
+            Event::Key(key) => self.on_key(*key),
+            // The items below the popup cannot be clicked.
+            Event::Mouse(_) if self.path_finder.is_some() => Refresh::Skip,
+            Event::Mouse(mouse) => match mouse.kind {
+                MouseEventKind::Down(MouseButton::Left) => self.on_click(mouse.column, mouse.row),
+                MouseEventKind::ScrollUp => {
+                    self.on_scroll(ScrollDirection::Up, mouse.column, mouse.row)
+                }
+                MouseEventKind::ScrollDown => {
+                    self.on_scroll(ScrollDirection::Down, mouse.column, mouse.row)
+                }
+                _ => Refresh::Skip,
+            },
+            // When resize happens, we need to redraw the widgets to fit the new size
+            Event::Resize(_, _) => Refresh::Update,
+            Event::FocusGained => self.on_focus_changed(true),
+            Event::FocusLost => self.on_focus_changed(false),
+            _ => Refresh::Skip,
+        }
+    }
+
+    /// Set the header, it is not shown if it is disabled in config.
     pub fn set_header(&mut self, ctx: HeaderContext) {
-        self.header = Some(Header::new(self.cfg, ctx));
+        self.header.set_context(ctx);
     }
 
-    fn draw(&mut self, frame: &mut Frame) {
-        self.refresh_area(frame);
+    /// Set how to reload the config, by the `reload_config` action, or when the config
+    /// file at `path` is modified. The `load` should return the parsed config.
+    pub fn set_config_source<F>(&mut self, path: Option<PathBuf>, load: F)
+    where
+        F: Fn() -> Result<Config> + 'static,
+    {
+        let modified = path.as_ref().and_then(|path| get_modified(path));
+        self.config_source = Some(ConfigSource {
+            path,
+            modified,
+            load: Box::new(load),
+        });
+    }
+
+    /// Reload the config if the config file is modified. This is called periodically
+    /// in [`App::show`], an app embedding the viewer can call it in its own loop.
+    pub fn check_config(&mut self) -> Refresh {
+        let source = match self.config_source.as_mut() {
+            Some(source) => source,
+            None => return Refresh::Skip,
+        };
+        let modified = match source.path.as_ref() {
+            Some(path) => get_modified(path),
+            None => return Refresh::Skip,
+        };
+        if modified == source.modified {
+            return Refresh::Skip;
+        }
+        source.modified = modified;
+        self.reload_config()
+    }
+
+    fn reload_config(&mut self) -> Refresh {
+        let source = match self.config_source.as_ref() {
+            Some(source) => source,
+            None => return Refresh::Skip,
+        };
+        // The current config is kept if the new one is invalid.
+        match (source.load)().context("reload config") {
+            Ok(cfg) => {
+                self.set_config(cfg);
+                self.message = None;
+            }
+            Err(err) => self.message = Some(format_error(&err)),
+        }
+        Refresh::Update
+    }
+
+    /// Use the new parsed config, the selected and opened items are kept. The layout and
+    /// the search options changed by actions are kept, unless they are also changed in
+    /// the config.
+    pub fn set_config(&mut self, cfg: Config) {
+        if cfg.layout.direction != self.cfg.layout.direction {
+            self.layout_direction = cfg.layout.direction;
+        }
+        if cfg.layout.tree_size != self.cfg.layout.tree_size {
+            self.layout_tree_size = cfg.layout.tree_size;
+        }
+        if cfg.search != self.cfg.search {
+            self.search_bar.reset_options(&cfg);
+        }
+        self.tree_overview.restyle(&cfg);
+        self.cfg = cfg;
+    }
+
+    /// Draw the app in the `area` of the frame.
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect) {
+        self.refresh_area(area);
 
         let selected = self.tree_overview.get_selected();
         if let Some(id) = selected {
@@ -123,22 +242,43 @@ impl<'a> App<'a> {
             // TODO: When we cannot find data, should warn user (maybe message in data block?)
         }
 
-        if let Some(header) = self.header.as_ref() {
-            if !self.skip_header {
-                header.draw(frame, self.header_area);
-            }
+        let message = self.error.as_deref().or(self.message.as_deref());
+        let filter = self.tree_overview.filter_status();
+        if self.header.is_shown(&self.cfg, message, filter) && !self.skip_header {
+            self.header
+                .draw(&self.cfg, frame, self.header_area, message, filter);
         }
 
         let tree_focus = matches!(self.focus, ElementInFocus::TreeOverview);
         self.tree_overview
-            .draw(frame, self.tree_overview_area, tree_focus);
+            .draw(&self.cfg, frame, self.tree_overview_area, tree_focus);
 
         let data_focus = matches!(self.focus, ElementInFocus::DataBlock);
         self.data_block
-            .draw(frame, self.data_block_area, data_focus);
+            .draw(&self.cfg, frame, self.data_block_area, data_focus);
+
+        if self.is_search_bar_shown() {
+            let status = match self.search_bar.mode() {
+                SearchMode::Search => self.tree_overview.search_status(),
+                SearchMode::Filter => self
+                    .tree_overview
+                    .filter_status()
+                    .map(|count| (None, count)),
+            };
+            self.search_bar
+                .draw(&self.cfg, frame, self.search_bar_area, status);
+        }
+
+        if let Some(path_finder) = self.path_finder.as_mut() {
+            path_finder.draw(&self.cfg, frame, area);
+        }
+    }
+
+    fn is_search_bar_shown(&self) -> bool {
+        self.search_bar.is_editing() || self.tree_overview.search_status().is_some()
     }
 
-    fn refresh_area(&mut self, frame: &Frame) {
+    fn refresh_area(&mut self, frame_area: Rect) {
         let tree_size = self.layout_tree_size;
         let data_size = 100_u16.saturating_sub(tree_size);
 
@@ -146,30 +286,45 @@ impl<'a> App<'a> {
         debug_assert_ne!(tree_size, 0);
         debug_assert_ne!(data_size, 0);
 
-        let frame_area = frame.size();
-        let main_area = match self.header {
-            Some(_) => {
-                let Rect { height, .. } = frame_area;
-                if height <= Self::HEADER_HEIGHT + 1 {
-                    // God knows under what circumstances such a small terminal would appear!
-                    // We will not render the header.
-                    self.skip_header = true;
-                    frame_area
-                } else {
-                    self.skip_header = false;
-                    self.header_area = Rect {
-                        height: Self::HEADER_HEIGHT,
-                        y: 0,
-                        ..frame_area
-                    };
-                    Rect {
-                        height: height.saturating_sub(Self::HEADER_HEIGHT),
-                        y: Self::HEADER_HEIGHT,
-                        ..frame_area
-                    }
+        let message = self.error.as_deref().or(self.message.as_deref());
+        let filter = self.tree_overview.filter_status();
+        let main_area = if self.header.is_shown(&self.cfg, message, filter) {
+            let Rect { height, .. } = frame_area;
+            if height <= Self::HEADER_HEIGHT + 1 {
+                // God knows under what circumstances such a small terminal would appear!
+                // We will not render the header.
+                self.skip_header = true;
+                frame_area
+            } else {
+                self.skip_header = false;
+                self.header_area = Rect {
+                    height: Self::HEADER_HEIGHT,
+                    ..frame_area
+                };
+                Rect {
+                    height: height.saturating_sub(Self::HEADER_HEIGHT),
+                    y: frame_area.y + Self::HEADER_HEIGHT,
+                    ..frame_area
                 }
             }
-            None => frame_area,
+        } else {
+            frame_area
+        };
+
+        // The search bar is not shown in a terminal too small, like the header.
+        let main_area = if self.is_search_bar_shown() && main_area.height > 2 {
+            self.search_bar_area = Rect {
+                y: main_area.bottom() - 1,
+                height: 1,
+                ..main_area
+            };
+            Rect {
+                height: main_area.height - 1,
+                ..main_area
+            }
+        } else {
+            self.search_bar_area = Rect::default();
+            main_area
         };
 
         match self.layout_direction {
@@ -199,6 +354,67 @@ impl<'a> App<'a> {
     }
 
     fn on_key(&mut self, key: KeyEvent) -> Refresh {
+        // The error is redrawn away even if the key does nothing.
+        let cleared = self.error.take().is_some();
+        match self.handle_key(key) {
+            Refresh::Skip if cleared => Refresh::Update,
+            refresh => refresh,
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Refresh {
+        if let Some(path_finder) = self.path_finder.as_mut() {
+            return match path_finder.on_key(key) {
+                FinderInput::Update => Refresh::Update,
+                FinderInput::Skip => Refresh::Skip,
+                FinderInput::Select(path) => {
+                    self.path_finder = None;
+                    self.tree_overview.select_path(&self.cfg, path);
+                    Refresh::Update
+                }
+                FinderInput::Close => {
+                    self.path_finder = None;
+                    Refresh::Update
+                }
+            };
+        }
+
+        if self.search_bar.is_editing() {
+            let mode = self.search_bar.mode();
+            match self.search_bar.on_key(key) {
+                SearchInput::Changed => return self.update_search(),
+                SearchInput::Confirm => {
+                    self.search_bar.stop();
+                    // Leave the search or the filter if nothing is matched.
+                    let matched = match mode {
+                        SearchMode::Search => {
+                            matches!(self.tree_overview.search_status(), Some((_, n)) if n > 0)
+                        }
+                        SearchMode::Filter => {
+                            matches!(self.tree_overview.filter_status(), Some(n) if n > 0)
+                        }
+                    };
+                    if self.search_bar.has_error() || !matched {
+                        match mode {
+                            SearchMode::Search => self.tree_overview.clear_search(&self.cfg, false),
+                            SearchMode::Filter => self.tree_overview.clear_filter(&self.cfg, true),
+                        };
+                    }
+                    return Refresh::Update;
+                }
+                SearchInput::Cancel => {
+                    self.search_bar.stop();
+                    match mode {
+                        SearchMode::Search => self.tree_overview.clear_search(&self.cfg, true),
+                        SearchMode::Filter => self.tree_overview.clear_filter(&self.cfg, true),
+                    };
+                    return Refresh::Update;
+                }
+                // Such as moving the cursor with the arrow keys while searching.
+                SearchInput::Ignored => {}
+            }
+        }
+
         let action = self.cfg.keys.get_key_action(key);
         if action.is_none() {
             return Refresh::Skip;
@@ -209,6 +425,48 @@ impl<'a> App<'a> {
             return Refresh::Quit;
         }
 
+        if let Action::ReloadConfig = action {
+            return self.reload_config();
+        }
+
+        if let Action::Search = action {
+            // The state before this search is restored if it is cancelled, rather than
+            // the one before the last search.
+            self.tree_overview.clear_search(&self.cfg, false);
+            self.search_bar.start(SearchMode::Search);
+            self.focus = ElementInFocus::TreeOverview;
+            return Refresh::Update;
+        }
+
+        if let Action::Filter = action {
+            // A new filter replaces the current one, the empty query shows all the items.
+            self.tree_overview.filter(&self.cfg, None);
+            self.search_bar.start(SearchMode::Filter);
+            self.focus = ElementInFocus::TreeOverview;
+            return Refresh::Update;
+        }
+
+        if let Action::FindPath = action {
+            let paths = self.tree_overview.get_paths();
+            self.path_finder = Some(PathFinder::new(paths));
+            self.focus = ElementInFocus::TreeOverview;
+            return Refresh::Update;
+        }
+
+        if let Action::ExpandEmbedded = action {
+            if !matches!(self.focus, ElementInFocus::TreeOverview) {
+                return Refresh::Skip;
+            }
+            return match self.tree_overview.expand_embedded(&self.cfg) {
+                Ok(true) => Refresh::Update,
+                Ok(false) => Refresh::Skip,
+                Err(err) => {
+                    self.error = Some(format_error(&err));
+                    Refresh::Update
+                }
+            };
+        }
+
         if let Action::Switch = action {
             match self.focus {
                 ElementInFocus::TreeOverview if self.can_switch_to_data_block() => {
@@ -258,7 +516,7 @@ impl<'a> App<'a> {
         }
 
         let update = match self.focus {
-            ElementInFocus::TreeOverview => self.tree_overview.on_key(action),
+            ElementInFocus::TreeOverview => self.tree_overview.on_key(&self.cfg, action),
             ElementInFocus::DataBlock => self.data_block.on_key(action),
             ElementInFocus::None => return Refresh::Skip,
         };
@@ -270,9 +528,23 @@ impl<'a> App<'a> {
         }
     }
 
+    /// Search or filter with the query in the search bar as it is typed.
+    fn update_search(&mut self) -> Refresh {
+        let query = self.search_bar.build_query();
+        // Keep the last matches until the query is fixed.
+        if self.search_bar.has_error() {
+            return Refresh::Update;
+        }
+        match self.search_bar.mode() {
+            SearchMode::Search => self.tree_overview.search(&self.cfg, query.as_ref()),
+            SearchMode::Filter => self.tree_overview.filter(&self.cfg, query.as_ref()),
+        };
+        Refresh::Update
+    }
+
     fn on_click(&mut self, column: u16, row: u16) -> Refresh {
         if let Some(index) = Self::get_row_inside(column, row, self.tree_overview_area) {
-            self.tree_overview.on_click(index);
+            self.tree_overview.on_click(&self.cfg, index);
             self.focus = ElementInFocus::TreeOverview;
             return Refresh::Update;
         }
@@ -341,3 +613,16 @@ impl<'a> App<'a> {
         }
     }
 }
+
+/// Returns the error in a single line, to be shown in the header.
+fn format_error(err: &anyhow::Error) -> String {
+    let message = format!("{err:#}");
+    let lines: Vec<_> = message.lines().map(str::trim).collect();
+    lines.join(" ")
+}
+
+/// Returns the modified time of the file, `None` if it cannot be read, like when it is
+/// being replaced by an editor.
+fn get_modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
@@ -9,8 +9,7 @@ use crate::config::keys::Action;
 use crate::config::Config;
 use crate::ui::app::ScrollDirection;
 
-pub(super) struct DataBlock<'a> {
-    cfg: &'a Config,
+pub(super) struct DataBlock {
     data: String,
 
     can_vertical_scroll: bool,
@@ -26,12 +25,11 @@ pub(super) struct DataBlock<'a> {
     last_area: Rect,
 }
 
-impl<'a> DataBlock<'a> {
+impl DataBlock {
     const SCROLL_RETAIN: usize = 5;
 
-    pub(super) fn new(cfg: &'a Config) -> Self {
+    pub(super) fn new() -> Self {
         Self {
-            cfg,
             data: String::new(),
             can_vertical_scroll: false,
             vertical_scroll: 0,
@@ -94,6 +92,16 @@ impl<'a> DataBlock<'a> {
         true
     }
 
+    pub(super) fn scroll_to(&mut self, row: usize) -> bool {
+        if !self.can_vertical_scroll {
+            return false;
+        }
+
+        self.vertical_scroll = row.min(self.vertical_scroll_last);
+        self.vertical_scroll_state = self.vertical_scroll_state.position(self.vertical_scroll);
+        true
+    }
+
     pub(super) fn scroll_down(&mut self, lines: usize) -> bool {
         if !self.can_vertical_scroll || self.vertical_scroll == self.vertical_scroll_last {
             return false;
@@ -187,12 +195,9 @@ impl<'a> DataBlock<'a> {
         self.last_area = area;
     }
 
-    pub(super) fn draw(&mut self, frame: &mut Frame, area: Rect, focus: bool) {
-        let (border_style, border_type) = super::get_border_style(
-            &self.cfg.colors.focus_border,
-            &self.cfg.colors.data.border,
-            focus,
-        );
+    pub(super) fn draw(&mut self, cfg: &Config, frame: &mut Frame, area: Rect, focus: bool) {
+        let (border_style, border_type) =
+            super::get_border_style(&cfg.colors.focus_border, &cfg.colors.data.border, focus);
 
         let block = Block::new()
             .border_type(border_type)
@@ -202,7 +207,7 @@ impl<'a> DataBlock<'a> {
             .title("Data Block");
 
         let widget = Paragraph::new(self.data.as_str())
-            .style(self.cfg.colors.data.text.style)
+            .style(cfg.colors.data.text.style)
             .block(block)
             .scroll((self.vertical_scroll as u16, self.horizontal_scroll as u16));
 
@@ -0,0 +1,93 @@
+use std::io::Stdout;
+
+use anyhow::Result;
+use crossterm::event::{Event, MouseEventKind};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Alignment, Constraint, Layout};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::Paragraph;
+use ratatui::{Frame, Terminal};
+
+use crate::config::keys::Action;
+use crate::config::Config;
+use crate::tree::ParseError;
+use crate::ui::app::ScrollDirection;
+use crate::ui::data_block::DataBlock;
+
+/// Show the data that cannot be parsed, scrolled to the error position, so that the user
+/// can look through the source around the error.
+pub struct ErrorPage<'a> {
+    cfg: &'a Config,
+    message: String,
+
+    source: String,
+    error_row: usize,
+    scrolled: bool,
+
+    data_block: DataBlock,
+}
+
+impl<'a> ErrorPage<'a> {
+    pub fn new(cfg: &'a Config, err: &ParseError, data: &str) -> Self {
+        let (source, error_row) = err.render_source(data, None);
+        let mut message = format!("⚠ {err}");
+        if let Some(hint) = err.hint(data) {
+            message.push_str(&format!(" (hint: {hint})"));
+        }
+        Self {
+            cfg,
+            message,
+            source,
+            error_row,
+            scrolled: false,
+            data_block: DataBlock::new(),
+        }
+    }
+
+    pub fn show(&mut self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> Result<()> {
+        terminal.draw(|frame| self.draw(frame))?;
+
+        loop {
+            let update = match crossterm::event::read()? {
+                Event::Key(key) => match self.cfg.keys.get_key_action(key) {
+                    Some(Action::Quit) => return Ok(()),
+                    Some(action) => self.data_block.on_key(action),
+                    None => false,
+                },
+                Event::Mouse(mouse) => match mouse.kind {
+                    MouseEventKind::ScrollUp => self.data_block.on_scroll(ScrollDirection::Up),
+                    MouseEventKind::ScrollDown => self.data_block.on_scroll(ScrollDirection::Down),
+                    _ => false,
+                },
+                Event::Resize(_, _) => {
+                    // The scroll state is reset when the area changes.
+                    self.scrolled = false;
+                    true
+                }
+                _ => false,
+            };
+
+            if update {
+                terminal.draw(|frame| self.draw(frame))?;
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [message_area, data_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(frame.size());
+
+        let message = Span::styled(self.message.as_str(), self.cfg.colors.item.warning.style);
+        let paragraph = Paragraph::new(Line::from(message)).alignment(Alignment::Center);
+        frame.render_widget(paragraph, message_area);
+
+        self.data_block.update_data(self.source.clone(), data_area);
+        if !self.scrolled {
+            // Keep the error line in the middle of the block.
+            let row = self.error_row.saturating_sub(data_area.height as usize / 2);
+            self.data_block.scroll_to(row);
+            self.scrolled = true;
+        }
+        self.data_block.draw(self.cfg, frame, data_area, true);
+    }
+}
@@ -1,65 +1,120 @@
 use std::borrow::Cow;
 
 use ratatui::layout::{Alignment, Rect};
-use ratatui::text::Span;
+use ratatui::text::{Line, Span};
 use ratatui::widgets::Paragraph;
 use ratatui::Frame;
 
 use crate::config::Config;
-use crate::tree::ContentType;
+use crate::encoding::Encoding;
 
 pub struct HeaderContext {
     version: String,
     data_source: Cow<'static, str>,
-    content_type: &'static str,
+    content_type: String,
+    encoding: &'static str,
     data_size: String,
+    warnings: Vec<String>,
 }
 
 impl HeaderContext {
-    pub fn new(source: Option<String>, content_type: ContentType, size: usize) -> Self {
+    pub fn new(
+        source: Option<String>,
+        content_type: String,
+        encoding: Encoding,
+        size: usize,
+    ) -> Self {
         let version = format!("otree {}", env!("CARGO_PKG_VERSION"));
         let source = source.map(Cow::Owned).unwrap_or(Cow::Borrowed("stdin"));
-        let content_type = match content_type {
-            ContentType::Toml => "toml",
-            ContentType::Yaml => "yaml",
-            ContentType::Json => "json",
-        };
-
         let data_size = humansize::format_size(size, humansize::BINARY);
 
         Self {
             version,
             data_source: source,
             content_type,
+            encoding: encoding.name(),
             data_size,
+            warnings: vec![],
         }
     }
 
+    /// Set the warnings found when parsing the data, they are shown after the header.
+    pub fn set_warnings(&mut self, warnings: Vec<String>) {
+        self.warnings = warnings;
+    }
+
     fn format(&self, s: &str) -> String {
         let s = s.replace("{version}", &self.version);
         let s = s.replace("{data_source}", &self.data_source);
-        let s = s.replace("{content_type}", self.content_type);
+        let s = s.replace("{content_type}", &self.content_type);
+        let s = s.replace("{encoding}", self.encoding);
         s.replace("{data_size}", &self.data_size)
     }
 }
 
-pub(super) struct Header<'a> {
-    cfg: &'a Config,
-    data: String,
+/// The header line, showing the data information and the warnings.
+#[derive(Default)]
+pub(super) struct Header {
+    ctx: Option<HeaderContext>,
 }
 
-impl<'a> Header<'a> {
-    pub(super) fn new(cfg: &'a Config, ctx: HeaderContext) -> Self {
-        Self {
-            cfg,
-            data: ctx.format(&cfg.header.format),
-        }
+impl Header {
+    pub(super) fn set_context(&mut self, ctx: HeaderContext) {
+        self.ctx = Some(ctx);
+    }
+
+    /// Returns whether the header is shown. The `message` is shown as a warning even if
+    /// the header is disabled in config, like the error of reloading the config. So is
+    /// the number of items matching the `filter`.
+    pub(super) fn is_shown(
+        &self,
+        cfg: &Config,
+        message: Option<&str>,
+        filter: Option<usize>,
+    ) -> bool {
+        self.context(cfg).is_some() || message.is_some() || filter.is_some()
     }
 
-    pub(super) fn draw(&self, frame: &mut Frame, area: Rect) {
-        let span = Span::styled(self.data.as_str(), self.cfg.colors.header.style);
+    pub(super) fn draw(
+        &self,
+        cfg: &Config,
+        frame: &mut Frame,
+        area: Rect,
+        message: Option<&str>,
+        filter: Option<usize>,
+    ) {
+        let mut spans = vec![];
+        let mut warnings = vec![];
+        if let Some(ctx) = self.context(cfg) {
+            spans.push(Span::styled(
+                ctx.format(&cfg.header.format),
+                cfg.colors.header.style,
+            ));
+            warnings.extend(ctx.warnings.iter().map(String::as_str));
+        }
+        if let Some(count) = filter {
+            let word = if count == 1 { "match" } else { "matches" };
+            spans.push(Span::styled(
+                format!("  [filter: {count} {word}]"),
+                cfg.colors.header.style,
+            ));
+        }
+        warnings.extend(message);
+        if !warnings.is_empty() {
+            spans.push(Span::styled(
+                format!("  ⚠ {}", warnings.join("; ")),
+                cfg.colors.item.warning.style,
+            ));
+        }
         // TODO: Allow user to customize alignment.
-        let paragraph = Paragraph::new(span).alignment(Alignment::Center);
+        let paragraph = Paragraph::new(Line::from(spans)).alignment(Alignment::Center);
         frame.render_widget(paragraph, area);
     }
+
+    fn context(&self, cfg: &Config) -> Option<&HeaderContext> {
+        if cfg.header.disable {
+            return None;
+        }
+        self.ctx.as_ref()
+    }
 }
@@ -0,0 +1,103 @@
+use std::io::Stdout;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use crossterm::event::{self, Event};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Alignment, Constraint, Layout};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::Paragraph;
+use ratatui::{Frame, Terminal};
+
+use crate::config::keys::Action;
+use crate::config::Config;
+use crate::tree::Progress;
+
+/// Show the progress while the data is loaded in another thread, the loading can be
+/// cancelled with the quit key.
+pub struct LoadingPage<'a> {
+    cfg: &'a Config,
+    source: String,
+    progress: &'a Progress,
+}
+
+impl<'a> LoadingPage<'a> {
+    /// The interval to check whether the loading is done and refresh the progress.
+    const REFRESH_INTERVAL: Duration = Duration::from_millis(20);
+    /// The page is only shown if the loading takes longer than this, to avoid flashing
+    /// when loading small data.
+    const SHOW_DELAY: Duration = Duration::from_millis(200);
+
+    pub fn new(cfg: &'a Config, source: String, progress: &'a Progress) -> Self {
+        Self {
+            cfg,
+            source,
+            progress,
+        }
+    }
+
+    /// Show the progress until `is_done` returns true. Returns `false` if the loading is
+    /// cancelled by the user, the loader stops at its next progress check.
+    pub fn show<F>(
+        &self,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        is_done: F,
+    ) -> Result<bool>
+    where
+        F: Fn() -> bool,
+    {
+        let start = Instant::now();
+        loop {
+            if is_done() {
+                return Ok(true);
+            }
+
+            if event::poll(Self::REFRESH_INTERVAL)? {
+                if let Event::Key(key) = event::read()? {
+                    if let Some(Action::Quit) = self.cfg.keys.get_key_action(key) {
+                        self.progress.cancel();
+                        return Ok(false);
+                    }
+                }
+            }
+
+            if start.elapsed() >= Self::SHOW_DELAY {
+                terminal.draw(|frame| self.draw(frame))?;
+            }
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [_, area, _] = Layout::vertical([
+            Constraint::Min(0),
+            Constraint::Length(3),
+            Constraint::Min(0),
+        ])
+        .areas(frame.size());
+
+        let bytes = humansize::format_size(self.progress.bytes(), humansize::BINARY);
+        let bytes = match self.progress.total() {
+            0 => bytes,
+            total => format!(
+                "{bytes} / {}",
+                humansize::format_size(total, humansize::BINARY)
+            ),
+        };
+        let status = format!("{bytes} read, {} nodes built", self.progress.nodes());
+        let hint = match self.cfg.keys.quit.first() {
+            Some(key) => format!("press {key} to cancel"),
+            None => String::new(),
+        };
+
+        let lines = vec![
+            Line::from(Span::styled(
+                format!("Loading {}", self.source),
+                self.cfg.colors.header.style,
+            )),
+            Line::from(status),
+            Line::from(Span::styled(hint, self.cfg.colors.item.description.style)),
+        ];
+        let paragraph = Paragraph::new(lines).alignment(Alignment::Center);
+        frame.render_widget(paragraph, area);
+    }
+}
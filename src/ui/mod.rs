@@ -1,6 +1,11 @@
 mod app;
 mod data_block;
+mod error_page;
 mod header;
+mod loading_page;
+mod path_finder;
+mod script;
+mod search_bar;
 mod tree_overview;
 
 use std::io::Stdout;
@@ -14,8 +19,11 @@ use ratatui::Terminal;
 
 use crate::config::colors::Color;
 
-pub use app::App;
+pub use app::{App, Refresh};
+pub use error_page::ErrorPage;
 pub use header::HeaderContext;
+pub use loading_page::LoadingPage;
+pub use script::{DumpOptions, Script};
 
 fn get_border_style(focus_color: &Color, normal_color: &Color, focus: bool) -> (Style, BorderType) {
     let color = if focus { focus_color } else { normal_color };
@@ -28,6 +36,8 @@ fn get_border_style(focus_color: &Color, normal_color: &Color, focus: bool) -> (
     (color.style, border_type)
 }
 
+/// Switch the terminal to raw mode and the alternate screen with mouse capture, it
+/// should be restored by [`restore`] at exit.
 pub fn start() -> Result<Terminal<CrosstermBackend<Stdout>>> {
     terminal::enable_raw_mode().context("enable terminal raw mode")?;
     let mut stdout = std::io::stdout();
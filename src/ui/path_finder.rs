@@ -0,0 +1,320 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::layout::{Alignment, Constraint, Layout, Rect};
+use ratatui::style::Style;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use ratatui::Frame;
+
+use crate::config::Config;
+use crate::tree::ItemPath;
+
+/// The popup listing the paths of all the items, fuzzy-filtered by the query, like the
+/// file finders in editors. The results are moved by `<up>`/`<down>` or
+/// `<ctrl-p>`/`<ctrl-n>`, `<enter>` selects the result, and `<esc>` closes the popup.
+pub(super) struct PathFinder {
+    paths: Vec<Vec<String>>,
+    /// The texts of the paths shown, the query is matched against them.
+    texts: Vec<String>,
+
+    query: String,
+    /// The index of the matched path and the positions of the matched chars, the best
+    /// match first.
+    results: Vec<(usize, Vec<usize>)>,
+    selected: usize,
+    /// The index of the first result shown.
+    offset: usize,
+}
+
+/// The result of a key pressed in the path finder.
+pub(super) enum FinderInput {
+    Update,
+    Skip,
+    /// Select the item at the path, and close the popup.
+    Select(Vec<String>),
+    Close,
+}
+
+impl PathFinder {
+    /// The size of the popup in percentage of the screen.
+    const WIDTH_PERCENT: u16 = 70;
+    const HEIGHT_PERCENT: u16 = 60;
+
+    pub(super) fn new(items: Vec<ItemPath>) -> Self {
+        let (paths, texts) = items.into_iter().map(|item| (item.path, item.text)).unzip();
+        let mut finder = Self {
+            paths,
+            texts,
+            query: String::new(),
+            results: vec![],
+            selected: 0,
+            offset: 0,
+        };
+        finder.update_results();
+        finder
+    }
+
+    pub(super) fn on_key(&mut self, key: KeyEvent) -> FinderInput {
+        match (key.code, key.modifiers) {
+            (KeyCode::Esc, _) => FinderInput::Close,
+            (KeyCode::Enter, _) => match self.results.get(self.selected) {
+                Some((idx, _)) => FinderInput::Select(self.paths[*idx].clone()),
+                None => FinderInput::Skip,
+            },
+            (KeyCode::Up, _) | (KeyCode::Char('p'), KeyModifiers::CONTROL) => {
+                if self.selected == 0 {
+                    return FinderInput::Skip;
+                }
+                self.selected -= 1;
+                FinderInput::Update
+            }
+            (KeyCode::Down, _) | (KeyCode::Char('n'), KeyModifiers::CONTROL) => {
+                if self.selected + 1 >= self.results.len() {
+                    return FinderInput::Skip;
+                }
+                self.selected += 1;
+                FinderInput::Update
+            }
+            (KeyCode::Backspace, _) => {
+                if self.query.pop().is_none() {
+                    return FinderInput::Skip;
+                }
+                self.update_results();
+                FinderInput::Update
+            }
+            (KeyCode::Char(char), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                self.query.push(char);
+                self.update_results();
+                FinderInput::Update
+            }
+            _ => FinderInput::Skip,
+        }
+    }
+
+    fn update_results(&mut self) {
+        self.selected = 0;
+        self.offset = 0;
+        if self.query.is_empty() {
+            self.results = (0..self.paths.len()).map(|idx| (idx, vec![])).collect();
+            return;
+        }
+
+        // Smart case, the query with uppercase chars is matched case sensitively.
+        let case_sensitive = self.query.chars().any(char::is_uppercase);
+        let query: Vec<char> = self.query.chars().collect();
+        let mut scored: Vec<_> = self
+            .texts
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, text)| {
+                let (score, positions) = fuzzy_match(text, &query, case_sensitive)?;
+                Some((score, idx, positions))
+            })
+            .collect();
+        // The paths with the same score are kept in the order they are shown.
+        scored.sort_by(|(a, a_idx, _), (b, b_idx, _)| b.cmp(a).then(a_idx.cmp(b_idx)));
+        self.results = scored
+            .into_iter()
+            .map(|(_, idx, positions)| (idx, positions))
+            .collect();
+    }
+
+    /// Draw the popup in the center of the `area`.
+    pub(super) fn draw(&mut self, cfg: &Config, frame: &mut Frame, area: Rect) {
+        let area = Self::popup_area(area);
+        frame.render_widget(Clear, area);
+
+        let title = format!(" Find Path {}/{} ", self.results.len(), self.paths.len());
+        let (border_style, border_type) =
+            super::get_border_style(&cfg.colors.focus_border, &cfg.colors.tree.border, true);
+        let block = Block::new()
+            .border_type(border_type)
+            .borders(Borders::ALL)
+            .border_style(border_style)
+            .title_alignment(Alignment::Center)
+            .title(title);
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let [input_area, list_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(inner);
+        let input = format!("> {}", self.query);
+        let cursor_x = input_area.x + Span::raw(input.as_str()).width() as u16;
+        if cursor_x < input_area.right() {
+            frame.set_cursor(cursor_x, input_area.y);
+        }
+        frame.render_widget(Paragraph::new(input), input_area);
+
+        // Keep the selected result in the list.
+        let height = list_area.height as usize;
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if height > 0 && self.selected >= self.offset + height {
+            self.offset = self.selected + 1 - height;
+        }
+
+        // The whole row of the selected result is highlighted, below the matched chars.
+        if self.selected < self.offset + height && !self.results.is_empty() {
+            let row = Rect {
+                y: list_area.y + (self.selected - self.offset) as u16,
+                height: 1,
+                ..list_area
+            };
+            frame
+                .buffer_mut()
+                .set_style(row, cfg.colors.tree.selected.style);
+        }
+
+        let match_style = cfg.colors.item.search_match.style;
+        let lines: Vec<Line> = self
+            .results
+            .iter()
+            .skip(self.offset)
+            .take(height)
+            .map(|(idx, positions)| highlight(&self.texts[*idx], positions, match_style))
+            .collect();
+        frame.render_widget(Paragraph::new(lines), list_area);
+    }
+
+    fn popup_area(area: Rect) -> Rect {
+        let [_, area, _] = Layout::vertical([
+            Constraint::Percentage((100 - Self::HEIGHT_PERCENT) / 2),
+            Constraint::Percentage(Self::HEIGHT_PERCENT),
+            Constraint::Min(0),
+        ])
+        .areas(area);
+        let [_, area, _] = Layout::horizontal([
+            Constraint::Percentage((100 - Self::WIDTH_PERCENT) / 2),
+            Constraint::Percentage(Self::WIDTH_PERCENT),
+            Constraint::Min(0),
+        ])
+        .areas(area);
+        area
+    }
+}
+
+/// Returns the line of the text, the chars at the `positions` are highlighted.
+fn highlight(text: &str, positions: &[usize], style: Style) -> Line<'static> {
+    let mut spans = vec![];
+    let mut current = String::new();
+    let mut matched = false;
+    let mut positions = positions.iter().peekable();
+    for (idx, char) in text.chars().enumerate() {
+        let is_match = positions.next_if_eq(&&idx).is_some();
+        if is_match != matched && !current.is_empty() {
+            let style = if matched { style } else { Style::default() };
+            spans.push(Span::styled(std::mem::take(&mut current), style));
+        }
+        matched = is_match;
+        current.push(char);
+    }
+    if !current.is_empty() {
+        let style = if matched { style } else { Style::default() };
+        spans.push(Span::styled(current, style));
+    }
+    Line::from(spans)
+}
+
+/// Match the query chars in order in the text, returns the score and the positions of
+/// the matched chars. The shortest window containing the query is used, the matches at
+/// the start of the names and the consecutive matches score higher.
+fn fuzzy_match(text: &str, query: &[char], case_sensitive: bool) -> Option<(i64, Vec<usize>)> {
+    let eq = |a: char, b: char| {
+        if case_sensitive {
+            a == b
+        } else {
+            a.to_lowercase().eq(b.to_lowercase())
+        }
+    };
+    let chars: Vec<char> = text.chars().collect();
+
+    // Find the end of the first match, then go back to find the start of the shortest
+    // window ending there.
+    let mut qi = 0;
+    let mut end = None;
+    for (idx, char) in chars.iter().enumerate() {
+        if eq(*char, query[qi]) {
+            qi += 1;
+            if qi == query.len() {
+                end = Some(idx);
+                break;
+            }
+        }
+    }
+    let end = end?;
+    let mut positions = Vec::with_capacity(query.len());
+    let mut qi = query.len();
+    for idx in (0..=end).rev() {
+        if eq(chars[idx], query[qi - 1]) {
+            positions.push(idx);
+            qi -= 1;
+            if qi == 0 {
+                break;
+            }
+        }
+    }
+    positions.reverse();
+
+    let mut score = 0;
+    let mut last = None;
+    for &pos in positions.iter() {
+        score += 16;
+        if pos == 0 || chars[pos - 1] == '/' {
+            score += 12;
+        }
+        match last {
+            Some(last) if pos == last + 1 => score += 8,
+            Some(last) => score -= (pos - last - 1) as i64,
+            None => {}
+        }
+        last = Some(pos);
+    }
+    // Prefer the shorter paths.
+    score -= chars.len() as i64 / 8;
+    Some((score, positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(query: &str) -> Vec<char> {
+        query.chars().collect()
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        let (_, positions) = fuzzy_match("/spec/name", &chars("name"), false).unwrap();
+        assert_eq!(positions, vec![6, 7, 8, 9]);
+
+        let (_, positions) = fuzzy_match("/spec/name", &chars("sn"), false).unwrap();
+        assert_eq!(positions, vec![1, 6]);
+
+        assert!(fuzzy_match("/spec/name", &chars("x"), false).is_none());
+        assert!(fuzzy_match("/spec", &chars("ceps"), false).is_none());
+    }
+
+    #[test]
+    fn test_fuzzy_match_shortest_window() {
+        // The first match ends at the first 'b', the window is shrunk from there.
+        let (_, positions) = fuzzy_match("/a/a/b/ab", &chars("ab"), false).unwrap();
+        assert_eq!(positions, vec![3, 5]);
+    }
+
+    #[test]
+    fn test_fuzzy_match_case() {
+        assert!(fuzzy_match("/Name", &chars("name"), false).is_some());
+        assert!(fuzzy_match("/Name", &chars("name"), true).is_none());
+        assert!(fuzzy_match("/Name", &chars("Name"), true).is_some());
+    }
+
+    #[test]
+    fn test_fuzzy_match_score() {
+        let score = |text: &str, query: &str| fuzzy_match(text, &chars(query), false).unwrap().0;
+        // The consecutive matches score higher.
+        assert!(score("/name", "name") > score("/nxaxmxe", "name"));
+        // The matches at the start of the names score higher.
+        assert!(score("/a/name", "n") > score("/a/xname", "n"));
+        // The shorter paths score higher.
+        assert!(score("/name", "name") > score("/name/with/a/long/suffix", "name"));
+    }
+}
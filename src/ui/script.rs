@@ -0,0 +1,474 @@
+use std::fmt::Write;
+
+use anyhow::{bail, Context, Result};
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use ratatui::backend::TestBackend;
+use ratatui::buffer::Buffer;
+use ratatui::style::{Color, Modifier};
+use ratatui::text::Span;
+use ratatui::Terminal;
+
+use crate::config::keys;
+use crate::ui::app::{App, Refresh};
+
+/// The events to drive the [`App`] without a terminal, the screens are rendered by the
+/// ratatui test backend and dumped as text. This is used to reproduce the behavior of the
+/// TUI deterministically.
+///
+/// Each line of the script is a step, it can be:
+///
+/// - The keys to press separated by spaces, in the config syntax, like `j j <enter>`.
+/// - `click <column> <row>`: Click the left mouse button at the cell.
+/// - `scroll-up <column> <row>` or `scroll-down <column> <row>`: Scroll the mouse wheel
+///   at the cell.
+/// - `resize <width> <height>`: Resize the screen.
+/// - `type <text>`: Type the text, like the search query.
+/// - `dump`: Dump the current screen.
+///
+/// The empty lines and the lines starting with `#` are ignored.
+pub struct Script {
+    steps: Vec<Step>,
+}
+
+struct Step {
+    line: usize,
+    source: String,
+    kind: StepKind,
+}
+
+enum StepKind {
+    Events(Vec<Event>),
+    Resize(u16, u16),
+    Dump,
+}
+
+/// How the screens are dumped by [`Script::run`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DumpOptions {
+    /// Dump the screen after each step, rather than only after `dump` steps and at the
+    /// end.
+    pub each_step: bool,
+    /// Dump the colors and modifiers of the cells below each row.
+    pub styles: bool,
+}
+
+impl Script {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut steps = Vec::new();
+        for (idx, source) in text.lines().enumerate() {
+            let source = source.trim();
+            if source.is_empty() || source.starts_with('#') {
+                continue;
+            }
+            let line = idx + 1;
+            let kind = Self::parse_step(source).with_context(|| format!("parse line {line}"))?;
+            steps.push(Step {
+                line,
+                source: source.to_string(),
+                kind,
+            });
+        }
+        Ok(Self { steps })
+    }
+
+    fn parse_step(source: &str) -> Result<StepKind> {
+        let fields: Vec<&str> = source.split_whitespace().collect();
+        let mouse = |kind: MouseEventKind| -> Result<StepKind> {
+            let (column, row) = Self::parse_numbers(&fields)?;
+            Ok(StepKind::Events(vec![Event::Mouse(MouseEvent {
+                kind,
+                column,
+                row,
+                modifiers: KeyModifiers::NONE,
+            })]))
+        };
+        match fields[0] {
+            "click" => mouse(MouseEventKind::Down(MouseButton::Left)),
+            "scroll-up" => mouse(MouseEventKind::ScrollUp),
+            "scroll-down" => mouse(MouseEventKind::ScrollDown),
+            "resize" => {
+                let (width, height) = Self::parse_numbers(&fields)?;
+                if width == 0 || height == 0 {
+                    bail!("the screen size cannot be zero");
+                }
+                Ok(StepKind::Resize(width, height))
+            }
+            "dump" if fields.len() == 1 => Ok(StepKind::Dump),
+            "type" => {
+                let text = source.strip_prefix("type").unwrap().trim_start();
+                let events = text
+                    .chars()
+                    .map(|char| Event::Key(KeyEvent::new(KeyCode::Char(char), KeyModifiers::NONE)))
+                    .collect();
+                Ok(StepKind::Events(events))
+            }
+            _ => {
+                let mut events = Vec::with_capacity(fields.len());
+                for key in fields {
+                    events.push(Event::Key(keys::parse_key_event(key)?));
+                }
+                Ok(StepKind::Events(events))
+            }
+        }
+    }
+
+    fn parse_numbers(fields: &[&str]) -> Result<(u16, u16)> {
+        if fields.len() != 3 {
+            bail!("'{}' requires 2 arguments", fields[0]);
+        }
+        let x = fields[1]
+            .parse()
+            .with_context(|| format!("invalid number '{}'", fields[1]))?;
+        let y = fields[2]
+            .parse()
+            .with_context(|| format!("invalid number '{}'", fields[2]))?;
+        Ok((x, y))
+    }
+
+    /// Run the steps against the app in a screen of the size, returns the dumped screens.
+    /// The screen is drawn only when the app asks for an update, the same as
+    /// [`App::show`]. The running stops when the quit key is pressed.
+    ///
+    /// The last screen is always dumped. If there are other screens, each screen starts
+    /// with a line showing the step it is dumped after.
+    pub fn run(&self, app: &mut App, width: u16, height: u16, opts: DumpOptions) -> Result<String> {
+        let mut terminal =
+            Terminal::new(TestBackend::new(width, height)).context("init terminal")?;
+        terminal.draw(|frame| app.draw(frame, frame.size()))?;
+
+        let mut screens = Vec::new();
+        'steps: for step in self.steps.iter() {
+            match &step.kind {
+                StepKind::Events(events) => {
+                    for event in events {
+                        match app.handle_event(event) {
+                            Refresh::Update => {}
+                            Refresh::Skip => continue,
+                            Refresh::Quit => break 'steps,
+                        }
+                        terminal.draw(|frame| app.draw(frame, frame.size()))?;
+                    }
+                }
+                StepKind::Resize(width, height) => {
+                    terminal.backend_mut().resize(*width, *height);
+                    app.handle_event(&Event::Resize(*width, *height));
+                    terminal.draw(|frame| app.draw(frame, frame.size()))?;
+                }
+                StepKind::Dump => {}
+            }
+            if opts.each_step || matches!(step.kind, StepKind::Dump) {
+                let title = format!("--- line {}: {} ---", step.line, step.source);
+                screens.push((title, terminal.backend().buffer().clone()));
+            }
+        }
+
+        let mut output = String::new();
+        let has_title = !screens.is_empty();
+        screens.push((
+            String::from("--- end ---"),
+            terminal.backend().buffer().clone(),
+        ));
+        for (title, buffer) in screens {
+            if has_title {
+                output.push_str(&title);
+                output.push('\n');
+            }
+            dump_buffer(&buffer, opts.styles, &mut output);
+        }
+        Ok(output)
+    }
+}
+
+/// Write the text of the buffer, the trailing spaces of the rows are trimmed. With
+/// `styles`, the cells with the same style are grouped, and the non-default groups are
+/// written below the row, like `  0..12 fg=Yellow bg=Reset BOLD`.
+fn dump_buffer(buffer: &Buffer, styles: bool, output: &mut String) {
+    let area = buffer.area;
+    for y in area.top()..area.bottom() {
+        let mut row = String::new();
+        // The cells covered by a wide symbol are skipped.
+        let mut skip = 0;
+        for x in area.left()..area.right() {
+            let symbol = buffer.get(x, y).symbol();
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            row.push_str(symbol);
+            skip = Span::raw(symbol).width().saturating_sub(1);
+        }
+        output.push_str(row.trim_end());
+        output.push('\n');
+
+        if styles {
+            dump_styles(buffer, y, output);
+        }
+    }
+}
+
+fn dump_styles(buffer: &Buffer, y: u16, output: &mut String) {
+    let area = buffer.area;
+    let style_of = |x: u16| {
+        let cell = buffer.get(x, y);
+        (cell.fg, cell.bg, cell.modifier)
+    };
+    let mut start = area.left();
+    while start < area.right() {
+        let style = style_of(start);
+        let mut end = start + 1;
+        while end < area.right() && style_of(end) == style {
+            end += 1;
+        }
+        let (fg, bg, modifier) = style;
+        if (fg, bg, modifier) != (Color::Reset, Color::Reset, Modifier::empty()) {
+            write!(output, "  {start}..{end} fg={fg:?} bg={bg:?}").unwrap();
+            for (name, _) in modifier.iter_names() {
+                write!(output, " {name}").unwrap();
+            }
+            output.push('\n');
+        }
+        start = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use anyhow::bail;
+
+    use crate::config::Config;
+    use crate::encoding::Encoding;
+    use crate::tree::{ContentType, Tree};
+
+    use super::*;
+
+    const DATA: &str = r#"{"name": "otree", "tags": ["tui", "json"], "meta": {"stars": 10}}"#;
+
+    fn new_config() -> Config {
+        let mut cfg = Config::default();
+        cfg.parse().unwrap();
+        cfg
+    }
+
+    fn new_app(cfg: Config) -> App {
+        let data = DATA.as_bytes().to_vec();
+        let tree = Tree::from_bytes(&cfg, data, ContentType::Json, Encoding::Auto).unwrap();
+        App::new(cfg, tree)
+    }
+
+    fn run(script: &str, width: u16, height: u16) -> String {
+        let script = Script::parse(script).unwrap();
+        let mut app = new_app(new_config());
+        script
+            .run(&mut app, width, height, DumpOptions::default())
+            .unwrap()
+    }
+
+    /// Returns the screens in the output, keyed by their titles.
+    fn screens(output: &str) -> Vec<(&str, Vec<&str>)> {
+        let mut screens: Vec<(&str, Vec<&str>)> = vec![];
+        for line in output.lines() {
+            if line.starts_with("--- ") {
+                screens.push((line, vec![]));
+            } else if let Some((_, rows)) = screens.last_mut() {
+                rows.push(line);
+            }
+        }
+        screens
+    }
+
+    #[test]
+    fn test_parse() {
+        let script =
+            Script::parse("# comment\n\nj k\ntype a b\nclick 1 2\nresize 10 5\ndump\n").unwrap();
+        let lines: Vec<_> = script.steps.iter().map(|step| step.line).collect();
+        assert_eq!(lines, vec![3, 4, 5, 6, 7]);
+        assert!(matches!(&script.steps[0].kind, StepKind::Events(events) if events.len() == 2));
+        assert!(matches!(&script.steps[1].kind, StepKind::Events(events) if events.len() == 3));
+        assert!(matches!(script.steps[3].kind, StepKind::Resize(10, 5)));
+        assert!(matches!(script.steps[4].kind, StepKind::Dump));
+
+        assert!(Script::parse("click 1").is_err());
+        assert!(Script::parse("resize a b").is_err());
+        assert!(Script::parse("<unknown-key>").is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let output = run("j\n<enter>\ndump\nj j\n", 60, 10);
+        let screens = screens(&output);
+        let titles: Vec<_> = screens.iter().map(|(title, _)| *title).collect();
+        assert_eq!(titles, vec!["--- line 3: dump ---", "--- end ---"]);
+        for (_, rows) in screens.iter() {
+            assert_eq!(rows.len(), 10);
+        }
+
+        // The first item is selected, and its value is shown in the data block.
+        let (_, rows) = &screens[0];
+        assert!(rows.iter().any(|row| row.contains("name str = \"otree\"")));
+        assert!(rows.iter().any(|row| row.contains("│otree")));
+    }
+
+    #[test]
+    fn test_run_without_dump() {
+        let output = run("j\n", 60, 10);
+        assert!(!output.contains("--- "));
+        assert_eq!(output.lines().count(), 10);
+    }
+
+    #[test]
+    fn test_run_each_step() {
+        let script = Script::parse("j\nresize 40 6\n").unwrap();
+        let mut app = new_app(new_config());
+        let opts = DumpOptions {
+            each_step: true,
+            styles: false,
+        };
+        let output = script.run(&mut app, 60, 10, opts).unwrap();
+        let screens = screens(&output);
+        let titles: Vec<_> = screens.iter().map(|(title, _)| *title).collect();
+        assert_eq!(
+            titles,
+            vec![
+                "--- line 1: j ---",
+                "--- line 2: resize 40 6 ---",
+                "--- end ---"
+            ]
+        );
+        assert_eq!(screens[0].1.len(), 10);
+        assert_eq!(screens[1].1.len(), 6);
+        assert!(screens[1].1.iter().all(|row| row.chars().count() <= 40));
+    }
+
+    #[test]
+    fn test_run_quit() {
+        // The steps after quitting are not run.
+        let output = run("dump\nq\ndump\n", 60, 10);
+        let titles: Vec<_> = screens(&output)
+            .into_iter()
+            .map(|(title, _)| title)
+            .collect();
+        assert_eq!(titles, vec!["--- line 1: dump ---", "--- end ---"]);
+    }
+
+    #[test]
+    fn test_run_search() {
+        let output = run("/\ntype stars\n<enter>\n", 60, 10);
+        let rows: Vec<_> = output.lines().collect();
+        assert!(rows.last().unwrap().starts_with("/stars"));
+        assert!(rows.last().unwrap().ends_with("1/1"));
+        assert!(rows.iter().any(|row| row.contains("stars num = 10")));
+    }
+
+    #[test]
+    fn test_run_filter() {
+        // Open and select "tags" before filtering, the state is restored by resetting.
+        let output = run(
+            "j j\n<enter>\nf\ntype stars\n<enter>\ndump\n<esc>\n",
+            60,
+            10,
+        );
+        let screens = screens(&output);
+        let (_, rows) = &screens[0];
+        assert!(rows[0].contains("[filter: 1 match]"));
+        assert!(rows.iter().any(|row| row.contains("▼ meta obj")));
+        assert!(rows.iter().any(|row| row.contains("stars num = 10")));
+        assert!(!rows
+            .iter()
+            .any(|row| row.contains("name") || row.contains("tags")));
+
+        let (_, rows) = &screens[1];
+        assert!(rows.iter().any(|row| row.contains("name str")));
+        assert!(rows.iter().any(|row| row.contains("▼ tags arr")));
+        assert!(rows.iter().any(|row| row.contains("1 str = \"json\"")));
+        assert!(rows.iter().any(|row| row.contains("▶ meta obj")));
+        assert!(!rows.iter().any(|row| row.contains("stars")));
+        // The data block shows the selected "tags".
+        assert!(rows.iter().any(|row| row.contains("│  \"tui\",")));
+    }
+
+    #[test]
+    fn test_run_filter_prune() {
+        // Only the matched items and their parents are kept.
+        let output = run("f\ntype json\n<enter>\n", 60, 10);
+        let rows: Vec<_> = output.lines().collect();
+        assert!(rows.iter().any(|row| row.contains("▼ tags arr")));
+        assert!(rows.iter().any(|row| row.contains("1 str = \"json\"")));
+        assert!(!rows.iter().any(|row| row.contains("tui")));
+        assert!(!rows
+            .iter()
+            .any(|row| row.contains("name") || row.contains("meta")));
+    }
+
+    #[test]
+    fn test_run_filter_cancel() {
+        // Cancelling the filter while typing restores the items.
+        let output = run("j\nf\ntype json\n<esc>\n", 60, 10);
+        let rows: Vec<_> = output.lines().collect();
+        assert!(!rows.iter().any(|row| row.contains("filter")));
+        assert!(rows.iter().any(|row| row.contains("name str")));
+        assert!(rows.iter().any(|row| row.contains("▶ tags arr")));
+        assert!(rows.iter().any(|row| row.contains("▶ meta obj")));
+        assert!(rows.iter().any(|row| row.contains("│otree")));
+    }
+
+    #[test]
+    fn test_set_config_search() {
+        let mut app = new_app(new_config());
+        let mut cfg = new_config();
+        cfg.search.regex = true;
+        app.set_config(cfg);
+
+        let script = Script::parse("/\n").unwrap();
+        let output = script
+            .run(&mut app, 60, 10, DumpOptions::default())
+            .unwrap();
+        assert!(output.lines().last().unwrap().ends_with("[regex]"));
+    }
+
+    #[test]
+    fn test_reload_config() {
+        let mut app = new_app(new_config());
+        let valid = Rc::new(Cell::new(true));
+        let load = {
+            let valid = Rc::clone(&valid);
+            move || {
+                if !valid.get() {
+                    bail!("invalid config");
+                }
+                let mut cfg = Config::default();
+                cfg.colors.focus_border.fg = Some(String::from("red"));
+                cfg.keys.move_down = vec![String::from("x")];
+                cfg.search.regex = true;
+                cfg.parse()?;
+                Ok(cfg)
+            }
+        };
+        app.set_config_source(None, load);
+        let opts = DumpOptions {
+            each_step: false,
+            styles: true,
+        };
+
+        // The colors, keys and search defaults are all reloaded.
+        let script = Script::parse("R\nx\n/\n").unwrap();
+        let output = script.run(&mut app, 60, 10, opts).unwrap();
+        assert!(output.contains("0..24 fg=Red bg=Reset BOLD"));
+        assert!(!output.contains("fg=Magenta bg=Reset BOLD"));
+        assert!(output.contains("│otree"));
+        assert!(output.contains("[regex]"));
+
+        // The current config is kept if the reloading fails.
+        valid.set(false);
+        let script = Script::parse("<esc>\nR\nx\n").unwrap();
+        let output = script.run(&mut app, 60, 10, opts).unwrap();
+        assert!(output.contains("reload config: invalid config"));
+        assert!(output.contains("0..24 fg=Red bg=Reset BOLD"));
+        assert!(output.contains("▶ tags arr"));
+        assert!(output.contains("│  \"tui\","));
+    }
+}
@@ -0,0 +1,197 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::layout::{Alignment, Constraint, Layout, Rect};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::Paragraph;
+use ratatui::Frame;
+
+use crate::config::Config;
+use crate::tree::{SearchQuery, SearchTarget};
+
+/// The input line of the search and the filter, shown at the bottom while searching. The
+/// options are switched by `<alt-r>` (regex), `<alt-c>` (case sensitive) and `<alt-k>`
+/// (keys, values or both) in the input.
+pub(super) struct SearchBar {
+    mode: SearchMode,
+    query: String,
+    regex: bool,
+    case_sensitive: bool,
+    target: SearchTarget,
+
+    /// The query is being edited, the keys are typed into the query.
+    editing: bool,
+    /// The error of the query, like an invalid regex.
+    error: Option<String>,
+}
+
+/// What the query in the input is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SearchMode {
+    /// Jump between the matches, see [`super::tree_overview::TreeOverview::search`].
+    Search,
+    /// Only show the matches, see [`super::tree_overview::TreeOverview::filter`].
+    Filter,
+}
+
+/// The result of a key pressed in the search input.
+pub(super) enum SearchInput {
+    /// The query or the options are changed.
+    Changed,
+    /// Stop editing, and keep the search.
+    Confirm,
+    /// Stop editing, and clear the search.
+    Cancel,
+    /// The key is not handled by the input.
+    Ignored,
+}
+
+impl SearchBar {
+    pub(super) fn new(cfg: &Config) -> Self {
+        Self {
+            mode: SearchMode::Search,
+            query: String::new(),
+            regex: cfg.search.regex,
+            case_sensitive: cfg.search.case_sensitive,
+            target: cfg.search.target,
+            editing: false,
+            error: None,
+        }
+    }
+
+    /// Reset the options to the defaults in the config, used when the config is
+    /// reloaded. The current search is kept, the options apply to the next query.
+    pub(super) fn reset_options(&mut self, cfg: &Config) {
+        self.regex = cfg.search.regex;
+        self.case_sensitive = cfg.search.case_sensitive;
+        self.target = cfg.search.target;
+    }
+
+    /// Start editing a new query.
+    pub(super) fn start(&mut self, mode: SearchMode) {
+        self.mode = mode;
+        self.query.clear();
+        self.error = None;
+        self.editing = true;
+    }
+
+    pub(super) fn stop(&mut self) {
+        self.editing = false;
+    }
+
+    pub(super) fn is_editing(&self) -> bool {
+        self.editing
+    }
+
+    pub(super) fn mode(&self) -> SearchMode {
+        self.mode
+    }
+
+    pub(super) fn on_key(&mut self, key: KeyEvent) -> SearchInput {
+        match (key.code, key.modifiers) {
+            (KeyCode::Esc, _) => SearchInput::Cancel,
+            (KeyCode::Enter, _) => SearchInput::Confirm,
+            // Delete the empty query to leave the search, like vim.
+            (KeyCode::Backspace, _) => match self.query.pop() {
+                Some(_) => SearchInput::Changed,
+                None => SearchInput::Cancel,
+            },
+            (KeyCode::Char('r'), KeyModifiers::ALT) => {
+                self.regex = !self.regex;
+                SearchInput::Changed
+            }
+            (KeyCode::Char('c'), KeyModifiers::ALT) => {
+                self.case_sensitive = !self.case_sensitive;
+                SearchInput::Changed
+            }
+            (KeyCode::Char('k'), KeyModifiers::ALT) => {
+                self.target = self.target.next();
+                SearchInput::Changed
+            }
+            (KeyCode::Char(char), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                self.query.push(char);
+                SearchInput::Changed
+            }
+            _ => SearchInput::Ignored,
+        }
+    }
+
+    /// Compile the query, returns `None` if the query is empty or invalid, the error is
+    /// shown in the input line.
+    pub(super) fn build_query(&mut self) -> Option<SearchQuery> {
+        self.error = None;
+        if self.query.is_empty() {
+            return None;
+        }
+        match SearchQuery::new(&self.query, self.regex, self.case_sensitive, self.target) {
+            Ok(query) => Some(query),
+            Err(err) => {
+                self.error = Some(format!("{err:#}"));
+                None
+            }
+        }
+    }
+
+    pub(super) fn has_error(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// Draw the input line, `status` is the index of the selected match and the number of
+    /// matches, see [`super::tree_overview::TreeOverview::search_status`]. There is no
+    /// selected match in the filter mode.
+    pub(super) fn draw(
+        &self,
+        cfg: &Config,
+        frame: &mut Frame,
+        area: Rect,
+        status: Option<(Option<usize>, usize)>,
+    ) {
+        let mut options = vec![];
+        if self.regex {
+            options.push("regex");
+        }
+        if self.case_sensitive {
+            options.push("case");
+        }
+        if self.target != SearchTarget::All {
+            options.push(self.target.name());
+        }
+        let mut right = vec![];
+        if !options.is_empty() {
+            right.push(Span::raw(format!("[{}] ", options.join(", "))));
+        }
+        match (self.error.as_ref(), status) {
+            (Some(err), _) => right.push(Span::styled(err.as_str(), cfg.colors.item.warning.style)),
+            (None, Some((_, 0))) if !self.query.is_empty() => {
+                right.push(Span::styled("no match", cfg.colors.item.warning.style))
+            }
+            (None, Some((Some(current), count))) => {
+                right.push(Span::raw(format!("{}/{count}", current + 1)))
+            }
+            (None, Some((None, count))) if count > 0 => {
+                let word = if count == 1 { "match" } else { "matches" };
+                right.push(Span::raw(format!("{count} {word}")))
+            }
+            _ => {}
+        }
+
+        let right = Line::from(right);
+        let right_width = right.width() as u16;
+        let [query_area, status_area] =
+            Layout::horizontal([Constraint::Min(0), Constraint::Length(right_width)]).areas(area);
+
+        let query = match self.mode {
+            SearchMode::Search => format!("/{}", self.query),
+            SearchMode::Filter => format!("Filter: {}", self.query),
+        };
+        if self.editing {
+            let x = query_area.x + Span::raw(query.as_str()).width() as u16;
+            if x < query_area.right() {
+                frame.set_cursor(x, query_area.y);
+            }
+        }
+        frame.render_widget(Paragraph::new(query), query_area);
+        frame.render_widget(
+            Paragraph::new(right).alignment(Alignment::Right),
+            status_area,
+        );
+    }
+}
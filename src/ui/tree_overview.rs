@@ -1,49 +1,95 @@
+use anyhow::Result;
 use ratatui::layout::{Alignment, Rect};
 use ratatui::widgets::{Block, Borders, Scrollbar, ScrollbarOrientation};
 use ratatui::Frame;
-use serde_json::Value;
 use tui_tree_widget::Tree as TreeWidget;
 use tui_tree_widget::TreeState;
 
 use crate::config::keys::Action;
 use crate::config::Config;
-use crate::tree::Tree;
+use crate::tree::{self, ItemPath, SearchQuery, Tree};
 use crate::ui::app::ScrollDirection;
 
-pub(super) struct TreeOverview<'a> {
-    cfg: &'a Config,
+pub(super) struct TreeOverview {
     state: Option<TreeState<String>>,
-    tree: Option<Tree<'a>>,
-    last_switches: Vec<(Tree<'a>, TreeState<String>)>,
-    root_switch: Option<(Tree<'a>, TreeState<String>)>,
+    tree: Option<Tree>,
+    last_switches: Vec<(Tree, TreeState<String>)>,
+    root_switch: Option<(Tree, TreeState<String>)>,
+    search: Option<Search>,
+    filter: Option<Filter>,
 }
 
-impl<'a> TreeOverview<'a> {
-    pub(super) fn new(cfg: &'a Config, tree: Tree<'a>) -> Self {
+/// The filter of the current tree, see [`TreeOverview::filter`].
+struct Filter {
+    /// The number of matches, `None` if all the items are shown.
+    count: Option<usize>,
+    /// The tree state before the filter, restored when the filter is cleared.
+    saved_state: TreeState<String>,
+}
+
+/// The search in the current tree, see [`TreeOverview::search`].
+struct Search {
+    matches: Vec<Vec<String>>,
+    /// The index of the selected match.
+    current: Option<usize>,
+    /// The tree state before the search, restored if the search is cancelled.
+    saved_state: TreeState<String>,
+}
+
+impl TreeOverview {
+    /// The maximum number of matches whose ancestors are opened by the search.
+    const MAX_OPENED_MATCHES: usize = 500;
+
+    pub(super) fn new(tree: Tree) -> Self {
         Self {
-            cfg,
             state: Some(TreeState::default()),
             tree: Some(tree),
             last_switches: vec![],
             root_switch: None,
+            search: None,
+            filter: None,
+        }
+    }
+
+    /// Build the items of all the trees again with the reloaded config. The tree states
+    /// are kept, so the selected and opened items do not change.
+    pub(super) fn restyle(&mut self, cfg: &Config) {
+        let trees = self
+            .tree
+            .iter_mut()
+            .chain(self.last_switches.iter_mut().map(|(tree, _)| tree))
+            .chain(self.root_switch.iter_mut().map(|(tree, _)| tree));
+        for tree in trees {
+            tree.restyle(cfg);
         }
     }
 
+    /// Returns the id of the selected item, which is the JSON Pointer of its path, see
+    /// [`tree::to_pointer`].
     pub(super) fn get_selected(&self) -> Option<String> {
         let selected = self.state().get_selected();
         if selected.is_empty() {
             return None;
         }
 
-        Some(selected.join("/"))
+        Some(tree::to_pointer(&selected))
     }
 
-    pub(super) fn get_data(&self, id: &str) -> Option<String> {
-        self.tree().details.get(id).map(|d| d.value.clone())
+    pub(super) fn get_data(&mut self, id: &str) -> Option<String> {
+        // The data in huge-file mode is not fully checked, the error is shown instead.
+        let data = match self.tree.as_mut().unwrap().get_detail(id)? {
+            Ok(data) => data,
+            Err(err) => format!("{err:#}"),
+        };
+        // The comments are shown above the value.
+        match self.tree().get_comments(id) {
+            Some(comments) => Some(format!("{comments}\n{data}")),
+            None => Some(data),
+        }
     }
 
-    pub(super) fn on_key(&mut self, action: Action) -> bool {
-        match action {
+    pub(super) fn on_key(&mut self, cfg: &Config, action: Action) -> bool {
+        let update = match action {
             Action::MoveUp => self.state_mut().key_up(),
             Action::MoveDown => self.state_mut().key_down(),
             Action::SelectFocus => self.state_mut().toggle_selected(),
@@ -53,32 +99,38 @@ impl<'a> TreeOverview<'a> {
             Action::PageDown => self.state_mut().scroll_down(3),
             Action::SelectFirst => self.state_mut().select_first(),
             Action::SelectLast => self.state_mut().select_last(),
-            Action::ChangeRoot => self.change_root(),
+            Action::ChangeRoot => self.change_root(cfg),
+            Action::Reset if self.search.is_some() => self.clear_search(cfg, false),
+            Action::Reset if self.filter.is_some() => self.clear_filter(cfg, true),
             Action::Reset => self.reset(),
+            Action::JumpAnchor => self.jump_anchor(),
+            Action::NextDocument => self.move_document(true),
+            Action::PrevDocument => self.move_document(false),
+            Action::SearchNext => self.move_search(true),
+            Action::SearchPrev => self.move_search(false),
             _ => false,
+        };
+        if update {
+            self.load_opened(cfg);
         }
+        update
     }
 
-    fn change_root(&mut self) -> bool {
+    fn change_root(&mut self, cfg: &Config) -> bool {
         let id = match self.get_selected() {
             Some(id) => id,
             None => return false,
         };
 
-        let value = match self.tree().details.get(id.as_str()) {
-            Some(detail) => {
-                if !matches!(detail.raw_value, Value::Array(_) | Value::Object(_)) {
-                    // We donot allow to change root to non-expandable value
-                    return false;
-                }
-                detail.raw_value.clone()
-            }
+        let new_tree = match self.tree().subtree(cfg, &id) {
+            Some(tree) => tree,
+            // We donot allow to change root to non-expandable value
             None => return false,
         };
 
-        let new_tree = Tree::from_value(self.cfg, value, self.tree().content_type)
-            .expect("build tree from change_root must success");
-
+        // The selected item is kept as the new root.
+        self.clear_search(cfg, false);
+        self.clear_filter(cfg, false);
         let current_tree = self.tree.take().unwrap();
         let current_state = self.state.take().unwrap();
         let switch = (current_tree, current_state);
@@ -95,6 +147,65 @@ impl<'a> TreeOverview<'a> {
         true
     }
 
+    fn jump_anchor(&mut self) -> bool {
+        let id = match self.get_selected() {
+            Some(id) => id,
+            None => return false,
+        };
+
+        let path = match self.tree().get_anchor_path(&id) {
+            Some(path) => path,
+            None => return false,
+        };
+
+        // All the ancestors of the anchor should be opened, otherwise the selection
+        // cannot be seen.
+        for idx in 1..path.len() {
+            self.state_mut().open(path[..idx].to_vec());
+        }
+        self.state_mut().select(path)
+    }
+
+    /// Expand the selected string item which contains json or yaml data, see
+    /// [`Tree::expand_embedded`]. Returns whether the item is expanded.
+    pub(super) fn expand_embedded(&mut self, cfg: &Config) -> Result<bool> {
+        let id = match self.get_selected() {
+            Some(id) => id,
+            None => return Ok(false),
+        };
+
+        let expanded = self.tree.as_mut().unwrap().expand_embedded(cfg, &id)?;
+        if !expanded {
+            return Ok(false);
+        }
+
+        let path = tree::parse_pointer(&id);
+        self.state_mut().open(path);
+        self.load_opened(cfg);
+        Ok(true)
+    }
+
+    fn move_document(&mut self, next: bool) -> bool {
+        let count = match self.tree().document_count() {
+            Some(count) => count,
+            None => return false,
+        };
+
+        let current = self
+            .state()
+            .get_selected()
+            .first()
+            .and_then(|idx| idx.parse::<usize>().ok());
+        let target = match (current, next) {
+            (None, _) => 0,
+            (Some(idx), true) if idx + 1 < count => idx + 1,
+            (Some(idx), false) if idx > 0 => idx - 1,
+            _ => return false,
+        };
+
+        self.state_mut().select(vec![target.to_string()])
+    }
+
     fn reset(&mut self) -> bool {
         let (reset_tree, reset_state) = match self.last_switches.pop() {
             Some((tree, state)) => (tree, state),
@@ -116,6 +227,169 @@ impl<'a> TreeOverview<'a> {
         true
     }
 
+    /// Search the current tree with the query, or clear the highlights if it is `None`.
+    /// The tree state is restored to the one before the search, the ancestors of the
+    /// matches are opened, and the first match from the selected item is selected.
+    /// Returns the number of matches.
+    pub(super) fn search(&mut self, cfg: &Config, query: Option<&SearchQuery>) -> usize {
+        let saved_state = match self.search.take() {
+            Some(search) => search.saved_state,
+            None => self.state().clone(),
+        };
+
+        let selected = saved_state.get_selected();
+        let matches = self.tree.as_mut().unwrap().search(cfg, query, &selected);
+        let mut state = saved_state.clone();
+        // Opening all the matches of a common query in a large document is slow, and makes
+        // the tree unreadable. The other matches are opened when moving to them.
+        for path in matches.paths.iter().take(Self::MAX_OPENED_MATCHES) {
+            for idx in 1..path.len() {
+                state.open(path[..idx].to_vec());
+            }
+        }
+        let current = if matches.paths.is_empty() {
+            None
+        } else {
+            let current = matches.start % matches.paths.len();
+            state.select(matches.paths[current].clone());
+            Some(current)
+        };
+        self.state = Some(state);
+
+        let count = matches.paths.len();
+        self.search = Some(Search {
+            matches: matches.paths,
+            current,
+            saved_state,
+        });
+        self.load_opened(cfg);
+        count
+    }
+
+    /// Clear the search, the tree state before the search is restored if `restore` is
+    /// true, like when the search is cancelled.
+    pub(super) fn clear_search(&mut self, cfg: &Config, restore: bool) -> bool {
+        let search = match self.search.take() {
+            Some(search) => search,
+            None => return false,
+        };
+        self.tree.as_mut().unwrap().search(cfg, None, &[]);
+        if restore {
+            self.state = Some(search.saved_state);
+        }
+        true
+    }
+
+    /// Returns the index of the selected match and the number of matches, `None` if
+    /// there is no search.
+    pub(super) fn search_status(&self) -> Option<(Option<usize>, usize)> {
+        let search = self.search.as_ref()?;
+        Some((search.current, search.matches.len()))
+    }
+
+    /// Only show the items matching the query and their ancestors, or show all the items
+    /// if it is `None`. The ancestors of the matches are opened, and the first match is
+    /// selected. Returns the number of matches.
+    pub(super) fn filter(&mut self, cfg: &Config, query: Option<&SearchQuery>) -> usize {
+        // The search matches may be hidden.
+        self.clear_search(cfg, false);
+        let saved_state = match self.filter.take() {
+            Some(filter) => filter.saved_state,
+            None => self.state().clone(),
+        };
+
+        let matches = self.tree.as_mut().unwrap().filter(cfg, query);
+        let mut state = match query {
+            Some(_) => TreeState::default(),
+            None => saved_state.clone(),
+        };
+        for path in matches.iter().take(Self::MAX_OPENED_MATCHES) {
+            for idx in 1..path.len() {
+                state.open(path[..idx].to_vec());
+            }
+        }
+        if let Some(path) = matches.first() {
+            state.select(path.clone());
+        }
+        self.state = Some(state);
+
+        let count = matches.len();
+        self.filter = Some(Filter {
+            count: query.map(|_| count),
+            saved_state,
+        });
+        self.load_opened(cfg);
+        count
+    }
+
+    /// Show all the items again, the tree state before the filter is restored if
+    /// `restore` is true.
+    pub(super) fn clear_filter(&mut self, cfg: &Config, restore: bool) -> bool {
+        let filter = match self.filter.take() {
+            Some(filter) => filter,
+            None => return false,
+        };
+        self.clear_search(cfg, false);
+        self.tree.as_mut().unwrap().filter(cfg, None);
+        if restore {
+            self.state = Some(filter.saved_state);
+        }
+        // The items hidden by the filter are shown unloaded.
+        self.load_opened(cfg);
+        true
+    }
+
+    /// Returns the number of items matching the filter, `None` if the tree is not
+    /// filtered.
+    pub(super) fn filter_status(&self) -> Option<usize> {
+        self.filter.as_ref().and_then(|filter| filter.count)
+    }
+
+    /// Returns the paths of all the items shown, listed by the path finder.
+    pub(super) fn get_paths(&self) -> Vec<ItemPath> {
+        self.tree().paths()
+    }
+
+    /// Open the ancestors of the item at the path and select it, like selecting a result
+    /// in the path finder.
+    pub(super) fn select_path(&mut self, cfg: &Config, path: Vec<String>) {
+        for idx in 1..path.len() {
+            self.state_mut().open(path[..idx].to_vec());
+        }
+        self.state_mut().select(path);
+        self.load_opened(cfg);
+    }
+
+    fn move_search(&mut self, next: bool) -> bool {
+        let selected = self.state().get_selected();
+        let search = match self.search.as_mut() {
+            Some(search) if !search.matches.is_empty() => search,
+            _ => return false,
+        };
+
+        // Move from the selected match, the cursor may be moved after the search.
+        let len = search.matches.len();
+        let current = search
+            .matches
+            .iter()
+            .position(|path| *path == selected)
+            .or(search.current);
+        let target = match (current, next) {
+            (None, true) => 0,
+            (None, false) => len - 1,
+            (Some(idx), true) => (idx + 1) % len,
+            (Some(idx), false) => (idx + len - 1) % len,
+        };
+        search.current = Some(target);
+        let path = search.matches[target].clone();
+
+        for idx in 1..path.len() {
+            self.state_mut().open(path[..idx].to_vec());
+        }
+        self.state_mut().select(path);
+        true
+    }
+
     fn close_parent(&mut self) -> bool {
         if !self.select_parent() {
             return false;
@@ -143,13 +417,27 @@ impl<'a> TreeOverview<'a> {
         Some(parent)
     }
 
-    pub(super) fn on_click(&mut self, index: u16) {
+    pub(super) fn on_click(&mut self, cfg: &Config, index: u16) {
         let offset = self.state().get_offset();
         let index = (index as usize) + offset;
 
         let changed = self.state_mut().select_visible_index(index);
         if !changed {
             self.state_mut().toggle_selected();
+            self.load_opened(cfg);
+        }
+    }
+
+    /// Build the children of the opened items, they are built on demand, see
+    /// [`Tree::load_children`].
+    fn load_opened(&mut self, cfg: &Config) {
+        let mut opened = self.state().get_all_opened();
+        // The parents must be loaded before their children.
+        opened.sort_by_key(Vec::len);
+
+        let tree = self.tree.as_mut().unwrap();
+        for path in opened {
+            tree.load_children(cfg, &path);
         }
     }
 
@@ -160,12 +448,9 @@ impl<'a> TreeOverview<'a> {
         }
     }
 
-    pub(super) fn draw(&mut self, frame: &mut Frame, area: Rect, focus: bool) {
-        let (border_style, border_type) = super::get_border_style(
-            &self.cfg.colors.focus_border,
-            &self.cfg.colors.tree.border,
-            focus,
-        );
+    pub(super) fn draw(&mut self, cfg: &Config, frame: &mut Frame, area: Rect, focus: bool) {
+        let (border_style, border_type) =
+            super::get_border_style(&cfg.colors.focus_border, &cfg.colors.tree.border, focus);
 
         let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
             .begin_symbol(None)
@@ -180,13 +465,13 @@ impl<'a> TreeOverview<'a> {
         let widget = TreeWidget::new(&self.tree.as_ref().unwrap().items)
             .unwrap()
             .experimental_scrollbar(Some(scrollbar))
-            .highlight_style(self.cfg.colors.tree.selected.style)
+            .highlight_style(cfg.colors.tree.selected.style)
             .block(block);
 
         frame.render_stateful_widget(widget, area, self.state.as_mut().unwrap());
     }
 
-    fn tree(&self) -> &Tree<'a> {
+    fn tree(&self) -> &Tree {
         self.tree.as_ref().unwrap()
     }
 